@@ -0,0 +1,186 @@
+//! Building the AppleScript commands which manage the login items of System Events,
+//! and parsing their output.
+//!
+//! The names and paths are always written as escaped string literals, so that a quote
+//! or a backslash in them cannot end the literal and run another command.
+//! The commands are run with `osascript -s s`, which prints the results as source,
+//! so a list of names is parsed back unambiguously, even if a name contains a comma.
+
+use crate::{Error, Result};
+
+/// A command on the login items, run by `osascript`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginItemCommand<'a> {
+    /// Add a login item at the end of the list
+    Make {
+        name: &'a str,
+        path: &'a str,
+        hidden: bool,
+    },
+    /// Delete the login item of the name
+    Delete { name: &'a str },
+    /// Get the names of every login item
+    GetNames,
+}
+
+impl LoginItemCommand<'_> {
+    /// Get the script of the command
+    pub fn to_script(&self) -> String {
+        let command = match self {
+            LoginItemCommand::Make { name, path, hidden } => format!(
+                "make login item at end with properties {{name:{}, path:{}, hidden:{}}}",
+                escape_string(name),
+                escape_string(path),
+                hidden
+            ),
+            LoginItemCommand::Delete { name } => {
+                format!("delete login item {}", escape_string(name))
+            }
+            LoginItemCommand::GetNames => "get the name of every login item".to_string(),
+        };
+        format!("tell application \"System Events\" to {}", command)
+    }
+}
+
+/// Quote the text as an AppleScript string literal
+///
+/// `"` and `\` are escaped with a backslash, and tabs and line breaks are written as
+/// `\t`, `\n` and `\r`. The other control characters have no escape, they are
+/// concatenated as `(character id N)`, so the result is an expression in that case.
+pub fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => {
+                escaped.push_str(&format!("\" & (character id {}) & \"", u32::from(c)))
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parse a list of strings printed by `osascript -s s`, such as `{"a", "b, c"}`
+///
+/// The strings are unescaped as [`escape_string`] escapes them.
+/// The items which are `missing value`, such as a login item without a name, are skipped,
+/// and no output at all is an empty list.
+///
+/// ## Errors
+///
+/// - the output is not a list of strings
+pub fn parse_string_list(output: &str) -> Result<Vec<String>> {
+    let mut parser = Parser {
+        rest: output.trim(),
+    };
+    if parser.rest.is_empty() {
+        return Ok(Vec::new());
+    }
+    parser.expect("{")?;
+    let mut items = Vec::new();
+    if !parser.eat("}") {
+        loop {
+            if parser.rest.starts_with('"') {
+                items.push(parser.string()?);
+            } else if !parser.eat("missing value") {
+                return Err(invalid("expected a string"));
+            }
+            if parser.eat("}") {
+                break;
+            }
+            parser.expect(",")?;
+        }
+    }
+    if !parser.rest.is_empty() {
+        return Err(invalid("unexpected output after the list"));
+    }
+    Ok(items)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidAppleScriptOutput(message.to_string())
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    /// Skip the token, and the whitespace after it, if the rest starts with it
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest.trim_start();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(Error::InvalidAppleScriptOutput(format!(
+                "expected `{}`",
+                token
+            )))
+        }
+    }
+
+    /// Read a string literal, concatenated with the `(character id N)` which have no escape
+    fn string(&mut self) -> Result<String> {
+        let mut string = self.literal()?;
+        while self.eat("&") {
+            if self.rest.starts_with('"') {
+                string.push_str(&self.literal()?);
+                continue;
+            }
+            self.expect("(")?;
+            self.expect("character id")?;
+            let end = self
+                .rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(self.rest.len());
+            let c = self.rest[..end]
+                .parse()
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| invalid("invalid character id"))?;
+            string.push(c);
+            self.rest = self.rest[end..].trim_start();
+            self.expect(")")?;
+        }
+        Ok(string)
+    }
+
+    /// Read a quoted literal, the line breaks may be escaped or not
+    fn literal(&mut self) -> Result<String> {
+        let mut literal = String::new();
+        let mut chars = self.rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = self.rest[i + 1..].trim_start();
+                    return Ok(literal);
+                }
+                '\\' => match chars.next() {
+                    Some((_, 't')) => literal.push('\t'),
+                    Some((_, 'n')) => literal.push('\n'),
+                    Some((_, 'r')) => literal.push('\r'),
+                    Some((_, c)) => literal.push(c),
+                    None => break,
+                },
+                c => literal.push(c),
+            }
+        }
+        Err(invalid("unterminated string"))
+    }
+}
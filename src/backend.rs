@@ -0,0 +1,69 @@
+//! The mechanisms which start the app at login.
+//!
+//! Each platform has its built-in backends, picked by the options of the
+//! [`AutoLaunchBuilder`](crate::AutoLaunchBuilder):
+//!
+//! - Linux: [`XdgAutostart`] and [`Systemd`], see [`LinuxLaunchMode`](crate::LinuxLaunchMode)
+//! - macOS: [`LaunchAgent`] and [`AppleScript`], see `use_launch_agent`
+//! - Windows: [`Registry`]
+//!
+//! Implement [`Backend`] to support another one, such as an in-house session manager,
+//! and pass it with [`AutoLaunchBuilder::set_backend`](crate::AutoLaunchBuilder::set_backend).
+//! A custom backend may delegate to the built-in ones, e.g. `XdgAutostart.enable(auto)`.
+
+use crate::{AutoLaunch, Result, Status};
+use std::{fmt, sync::Arc};
+
+/// Starts the app described by an [`AutoLaunch`] at login
+///
+/// The methods of [`AutoLaunch`] with the same names call the backend with itself.
+pub trait Backend: fmt::Debug + Send + Sync {
+    /// Register the app to be started at login
+    fn enable(&self, auto: &AutoLaunch) -> Result<()>;
+
+    /// Unregister the app
+    fn disable(&self, auto: &AutoLaunch) -> Result<()>;
+
+    /// Get the [`Status`] of the registration
+    fn status(&self, auto: &AutoLaunch) -> Result<Status>;
+
+    /// Describe where the app is registered, e.g. the path of the written file
+    fn describe(&self, auto: &AutoLaunch) -> String;
+}
+
+/// The desktop entry in the XDG autostart dir
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct XdgAutostart;
+
+/// The systemd user service wanted by `graphical-session.target`
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Systemd;
+
+/// The plist in `~/Library/LaunchAgents`
+#[cfg(target_os = "macos")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LaunchAgent;
+
+/// The login item of System Events, managed with AppleScript
+#[cfg(target_os = "macos")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AppleScript;
+
+/// The value under the `Run` key of the registry
+#[cfg(target_os = "windows")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Registry;
+
+/// A shared custom [`Backend`], two backends are equal if they are the same instance
+#[derive(Debug, Clone)]
+pub(crate) struct SharedBackend(pub(crate) Arc<dyn Backend>);
+
+impl PartialEq for SharedBackend {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedBackend {}
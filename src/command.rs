@@ -0,0 +1,49 @@
+//! Running the external commands, such as `systemctl`.
+
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+use std::{
+    fmt, io,
+    process::{Command, Output},
+};
+
+/// Runs the external commands on behalf of the backends
+///
+/// The default one is [`SystemCommandRunner`].
+/// Implement it to record the invocations and return canned output in tests.
+pub trait CommandRunner: fmt::Debug + Send + Sync {
+    /// Run the `program` with the `args` and wait for its output
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<Output>;
+}
+
+/// Runs the commands with [`std::process::Command`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        Command::new(program).args(args).output()
+    }
+}
+
+/// A shared [`CommandRunner`], two runners are equal if they are the same instance
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone)]
+pub(crate) struct SharedRunner(pub(crate) Arc<dyn CommandRunner>);
+
+#[cfg(target_os = "linux")]
+impl Default for SharedRunner {
+    fn default() -> Self {
+        Self(Arc::new(SystemCommandRunner))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PartialEq for SharedRunner {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Eq for SharedRunner {}
@@ -0,0 +1,482 @@
+//! Reading and writing [Desktop Entry] files.
+//!
+//! [Desktop Entry]: https://specifications.freedesktop.org/desktop-entry-spec/latest/
+
+use crate::{Error, Result};
+use std::fmt;
+
+/// The name of the main group of a desktop entry
+pub const MAIN_GROUP: &str = "Desktop Entry";
+
+/// Characters which force an `Exec` argument to be quoted
+const RESERVED: &[char] = &[
+    ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')',
+    '`',
+];
+
+/// Characters which must be escaped with a backslash inside a quoted `Exec` argument
+const QUOTE_ESCAPED: &[char] = &['"', '`', '$', '\\'];
+
+/// A desktop entry file, parsed into groups of `Key=Value` pairs
+///
+/// The parser is lenient: lines which are neither a comment, a `[Group]` header
+/// nor a `Key=Value` pair are ignored, as the readers of autostart entries do.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// A comment, blank or invalid line
+    Other(String),
+    /// A `[Group]` header
+    Group(String),
+    /// A `Key=Value` pair, the value is kept escaped
+    Entry { key: String, value: String },
+}
+
+impl DesktopEntry {
+    /// Parse the content of a desktop entry file
+    pub fn parse(text: &str) -> DesktopEntry {
+        let lines = text
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                    return Line::Group(trimmed[1..trimmed.len() - 1].to_string());
+                }
+                if trimmed.starts_with('#') {
+                    return Line::Other(line.to_string());
+                }
+                match trimmed.split_once('=') {
+                    Some((key, value)) if !key.trim().is_empty() => Line::Entry {
+                        key: key.trim().to_string(),
+                        value: value.trim().to_string(),
+                    },
+                    _ => Line::Other(line.to_string()),
+                }
+            })
+            .collect();
+        DesktopEntry { lines }
+    }
+
+    /// Get the raw (escaped) value of a key in a group, the last one wins if duplicated
+    pub fn get_raw(&self, group: &str, key: &str) -> Option<&str> {
+        let mut current = None;
+        let mut found = None;
+        for line in &self.lines {
+            match line {
+                Line::Group(name) => current = Some(name.as_str()),
+                Line::Entry { key: k, value } if current == Some(group) && k == key => {
+                    found = Some(value.as_str())
+                }
+                _ => {}
+            }
+        }
+        found
+    }
+
+    /// Get a string value of the `[Desktop Entry]` group
+    pub fn get_string(&self, key: &str) -> Option<String> {
+        self.get_raw(MAIN_GROUP, key).map(unescape_value)
+    }
+
+    /// Get a boolean value of the `[Desktop Entry]` group
+    ///
+    /// Values other than `true`, `false`, `1` and `0` are treated as missing.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get_raw(MAIN_GROUP, key)? {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Get a list of strings, separated by `;`, of the `[Desktop Entry]` group
+    pub fn get_strings(&self, key: &str) -> Vec<String> {
+        self.get_raw(MAIN_GROUP, key)
+            .map(split_list)
+            .unwrap_or_default()
+    }
+
+    /// Set the raw (escaped) value of a key in a group
+    ///
+    /// An existing key is updated in place, otherwise the key is appended to the group,
+    /// and the group is appended to the file if missing.
+    /// If the key is duplicated, the last one is updated, since it wins,
+    /// and the others are removed.
+    pub fn set_raw(&mut self, group: &str, key: &str, value: &str) {
+        let mut current = None;
+        // the index after the group header or its last key
+        let mut insert_at = None;
+        let mut found = Vec::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Group(name) => {
+                    current = Some(name.as_str());
+                    if current == Some(group) {
+                        insert_at = Some(i + 1);
+                    }
+                }
+                Line::Entry { key: k, .. } if current == Some(group) => {
+                    if k == key {
+                        found.push(i);
+                    }
+                    insert_at = Some(i + 1);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((&last, duplicates)) = found.split_last() {
+            if let Line::Entry { value: v, .. } = &mut self.lines[last] {
+                *v = value.to_string();
+            }
+            for &i in duplicates.iter().rev() {
+                self.lines.remove(i);
+            }
+            return;
+        }
+
+        let entry = Line::Entry {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        match insert_at {
+            Some(i) => self.lines.insert(i, entry),
+            None => {
+                let blank = |line: &Line| matches!(line, Line::Other(l) if l.trim().is_empty());
+                if self.lines.last().is_some_and(|line| !blank(line)) {
+                    self.lines.push(Line::Other(String::new()));
+                }
+                self.lines.push(Line::Group(group.to_string()));
+                self.lines.push(entry);
+            }
+        }
+    }
+
+    /// Set a string value of the `[Desktop Entry]` group
+    pub fn set_string(&mut self, key: &str, value: &str) {
+        self.set_raw(MAIN_GROUP, key, &escape_value(value));
+    }
+
+    /// Set a boolean value of the `[Desktop Entry]` group
+    pub fn set_bool(&mut self, key: &str, value: bool) {
+        self.set_raw(MAIN_GROUP, key, if value { "true" } else { "false" });
+    }
+
+    /// Set a list of strings, separated by `;`, of the `[Desktop Entry]` group
+    pub fn set_strings(&mut self, key: &str, values: &[impl AsRef<str>]) {
+        let value = values
+            .iter()
+            .map(|v| escape_value(v.as_ref()).replace(';', "\\;") + ";")
+            .collect::<String>();
+        self.set_raw(MAIN_GROUP, key, &value);
+    }
+
+    /// Remove a key from a group
+    pub fn remove(&mut self, group: &str, key: &str) {
+        let mut current = None;
+        self.lines.retain(|line| match line {
+            Line::Group(name) => {
+                current = Some(name.clone());
+                true
+            }
+            Line::Entry { key: k, .. } => current.as_deref() != Some(group) || k != key,
+            Line::Other(_) => true,
+        });
+    }
+}
+
+/// The keys of the `[Desktop Entry]` group which decide how an autostart entry is started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutostartEntry {
+    /// `Name`
+    pub name: Option<String>,
+    /// `Comment`
+    pub comment: Option<String>,
+    /// `Icon`
+    pub icon: Option<String>,
+    /// `Exec`, unescaped but with the arguments still quoted, see [`AutostartEntry::args`]
+    pub exec: Option<String>,
+    /// `Hidden`, the entry is deleted and must not be started
+    pub hidden: bool,
+    /// `X-GNOME-Autostart-enabled`
+    pub gnome_autostart_enabled: Option<bool>,
+    /// `OnlyShowIn`, the desktops the entry is only started in
+    pub only_show_in: Vec<String>,
+    /// `NotShowIn`, the desktops the entry is not started in
+    pub not_show_in: Vec<String>,
+    /// `X-GNOME-Autostart-Delay`, in seconds
+    pub gnome_autostart_delay: Option<u32>,
+    /// `X-GNOME-Autostart-Phase`
+    pub gnome_autostart_phase: Option<String>,
+    /// `X-KDE-autostart-phase`
+    pub kde_autostart_phase: Option<u32>,
+}
+
+impl AutostartEntry {
+    /// Parse the content of a desktop entry file
+    pub fn parse(text: &str) -> AutostartEntry {
+        AutostartEntry::from(&DesktopEntry::parse(text))
+    }
+
+    /// Get the program and its args of the `Exec`
+    ///
+    /// ## Errors
+    ///
+    /// - `Exec` is missing or empty
+    /// - a quoted argument is not terminated
+    pub fn args(&self) -> Result<Vec<String>> {
+        let args = parse_exec(self.exec.as_deref().unwrap_or_default())?;
+        if args.is_empty() {
+            return Err(Error::InvalidDesktopEntry("missing Exec".into()));
+        }
+        Ok(args)
+    }
+
+    /// Check whether the entry is started in any of the `desktops`,
+    /// the names listed in `$XDG_CURRENT_DESKTOP`
+    pub fn is_enabled_in(&self, desktops: &[String]) -> bool {
+        if self.hidden || self.gnome_autostart_enabled == Some(false) {
+            return false;
+        }
+        if !self.only_show_in.is_empty() && !desktops.iter().any(|d| self.only_show_in.contains(d))
+        {
+            return false;
+        }
+        !desktops.iter().any(|d| self.not_show_in.contains(d))
+    }
+}
+
+impl From<&DesktopEntry> for AutostartEntry {
+    fn from(entry: &DesktopEntry) -> Self {
+        AutostartEntry {
+            name: entry.get_string("Name"),
+            comment: entry.get_string("Comment"),
+            icon: entry.get_string("Icon"),
+            exec: entry.get_string("Exec"),
+            hidden: entry.get_bool("Hidden") == Some(true),
+            gnome_autostart_enabled: entry.get_bool("X-GNOME-Autostart-enabled"),
+            only_show_in: entry.get_strings("OnlyShowIn"),
+            not_show_in: entry.get_strings("NotShowIn"),
+            gnome_autostart_delay: entry
+                .get_string("X-GNOME-Autostart-Delay")
+                .and_then(|v| v.parse().ok()),
+            gnome_autostart_phase: entry.get_string("X-GNOME-Autostart-Phase"),
+            kde_autostart_phase: entry
+                .get_string("X-KDE-autostart-phase")
+                .and_then(|v| v.parse().ok()),
+        }
+    }
+}
+
+impl fmt::Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(line) => writeln!(f, "{}", line)?,
+                Line::Group(name) => writeln!(f, "[{}]", name)?,
+                Line::Entry { key, value } => writeln!(f, "{}={}", key, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a raw list value on the unescaped `;` and unescape every item
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => item.push(';'),
+                Some(c) => {
+                    item.push('\\');
+                    item.push(c);
+                }
+                None => item.push('\\'),
+            },
+            ';' => items.push(unescape_value(&std::mem::take(&mut item))),
+            c => item.push(c),
+        }
+    }
+    if !item.is_empty() {
+        items.push(unescape_value(&item));
+    }
+    items
+}
+
+/// Build the value of the `Exec` key from a program and its args
+///
+/// Every argument comes back unchanged from [`parse_exec`].
+/// The result still has to be passed through [`escape_value`] before it is written to a file.
+pub fn format_exec(program: &str, args: &[impl AsRef<str>]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(|s| s.as_ref()))
+        .map(quote_exec_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote a single `Exec` argument
+fn quote_exec_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty() || arg.contains(RESERVED);
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    if needs_quotes {
+        quoted.push('"');
+    }
+    for c in arg.chars() {
+        match c {
+            '%' => quoted.push_str("%%"),
+            c if needs_quotes && QUOTE_ESCAPED.contains(&c) => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    if needs_quotes {
+        quoted.push('"');
+    }
+    quoted
+}
+
+/// Split the value of the `Exec` key into the program and its args
+///
+/// The `exec` should already be unescaped with [`unescape_value`].
+/// `%%` becomes `%` and the other field codes are dropped, as there are no files or URLs
+/// to expand them to at login.
+///
+/// ## Errors
+///
+/// - a quoted argument is not terminated
+pub fn parse_exec(exec: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = exec.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut arg = String::new();
+        // an argument only made of field codes expands to nothing
+        let mut started = false;
+        while let Some(c) = chars.next_if(|c| *c != ' ' && *c != '\t') {
+            match c {
+                '"' => {
+                    started = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.next_if(|c| QUOTE_ESCAPED.contains(c)) {
+                                Some(c) => arg.push(c),
+                                None => arg.push('\\'),
+                            },
+                            Some('%') => {
+                                if chars.next_if_eq(&'%').is_some() {
+                                    arg.push('%');
+                                } else {
+                                    chars.next();
+                                }
+                            }
+                            Some(c) => arg.push(c),
+                            None => {
+                                return Err(Error::InvalidDesktopEntry(format!(
+                                    "unterminated quote in Exec: {}",
+                                    exec
+                                )))
+                            }
+                        }
+                    }
+                }
+                '%' => {
+                    if chars.next_if_eq(&'%').is_some() {
+                        started = true;
+                        arg.push('%');
+                    } else {
+                        chars.next();
+                    }
+                }
+                c => {
+                    started = true;
+                    arg.push(c);
+                }
+            }
+        }
+        if started {
+            args.push(arg);
+        }
+    }
+
+    Ok(args)
+}
+
+/// Check whether the locale of a localized key, such as `Name[locale]`, is valid
+///
+/// The spec defines it as `lang_COUNTRY.ENCODING@MODIFIER`, where only `lang` is required,
+/// e.g. `de`, `pt_BR`, `en_US.UTF-8` or `sr@latin`.
+pub fn is_valid_locale(locale: &str) -> bool {
+    let (rest, modifier) = locale.split_once('@').unwrap_or((locale, "x"));
+    let (rest, encoding) = rest.split_once('.').unwrap_or((rest, "x"));
+    let (lang, country) = rest.split_once('_').unwrap_or((rest, "x"));
+    let is_word = |part: &str, extra: &[char]| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || extra.contains(&c))
+    };
+    !lang.is_empty()
+        && lang.chars().all(|c| c.is_ascii_alphabetic())
+        && is_word(country, &[])
+        && is_word(encoding, &['-'])
+        && is_word(modifier, &[])
+}
+
+/// Escape a value of type string so it can be written after the `=` of a key
+pub fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            // surrounding spaces would be trimmed by the readers
+            ' ' if i == 0 || i == last => escaped.push_str("\\s"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unescape a value of type string read after the `=` of a key
+pub fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
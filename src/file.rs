@@ -0,0 +1,61 @@
+use crate::Result;
+use std::{
+    fs::{self, File, OpenOptions, Permissions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The mode of the written files, `rw-r--r--`
+const FILE_MODE: u32 = 0o644;
+
+/// Distinguishes the temporary files of the concurrent writes in this process
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Write the file atomically, creating its parent dir if needed
+///
+/// The data is written to a temporary file in the same dir, synced to the disk,
+/// given the mode `0644` and renamed over the target,
+/// so a crash never leaves a truncated or partially written file behind.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dir.join(format!(
+        ".{}.{}-{}.tmp",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = write_and_rename(&tmp, path, data);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+
+    // make the rename durable, not every file system supports syncing a dir
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_and_rename(tmp: &Path, path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(FILE_MODE)
+        .open(tmp)?;
+    file.write_all(data)?;
+    // the mode given on creation is restricted by the umask
+    file.set_permissions(Permissions::from_mode(FILE_MODE))?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
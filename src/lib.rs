@@ -37,6 +37,7 @@
 //! **Note**:
 //! - The `app_path` should be a absolute path and exists. Otherwise, it will cause an error when `enable`.
 //! - In case using AppleScript, the `app_name` should be same as the basename of `app_path`, or it will be corrected automatically.
+//! - In case using AppleScript, the `args` are not passed to the app. Use [`AutoLaunchBuilder::set_start_hidden`] to hide the app on launch.
 //!
 //! ```rust
 //! # #[cfg(target_os = "macos")]
@@ -60,6 +61,14 @@
 //! # }
 //! ```
 //!
+//! ### Linux
+//!
+//! On Linux, it will write a desktop entry into the XDG autostart dir (`~/.config/autostart`) by default,
+//! which is started by the desktop session at login.
+//! It can also write a systemd user service (`~/.config/systemd/user`), wanted by `graphical-session.target`,
+//! for background agents which need to be restarted on failure or log into the journal.
+//! To change this behavior, you can use [`AutoLaunchBuilder::set_linux_launch_mode`].
+//!
 //! ### Windows
 //!
 //! On Windows, it will add a registry entry under either `\HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Run` (system-wide) or
@@ -118,6 +127,15 @@
 //! ```
 //!
 
+use backend::Backend;
+use command::CommandRunner;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("app_name shouldn't be None")]
@@ -130,18 +148,44 @@ pub enum Error {
     AppPathIsNotAbsolute(std::path::PathBuf),
     #[error("Failed to execute apple script with status: {0}")]
     AppleScriptFailed(i32),
+    #[error("invalid AppleScript output: {0}")]
+    InvalidAppleScriptOutput(String),
+    #[error("Failed to execute systemctl with status: {0}")]
+    SystemctlFailed(i32),
     #[error("Unsupported target os")]
     UnsupportedOS,
+    #[error("invalid environment variable name: {0:?}")]
+    InvalidEnvName(String),
+    #[error("app path cannot be run by `env` with the envs, it contains `=`: {0}")]
+    AppPathContainsEquals(std::path::PathBuf),
+    #[error("invalid locale: {0:?}")]
+    InvalidLocale(String),
+    #[error("`{0}` is not supported by this backend")]
+    UnsupportedOption(&'static str),
+    #[error("home dir is not found, specify it with `set_home_dir`")]
+    HomeDirNotFound,
+    #[error("invalid desktop entry: {0}")]
+    InvalidDesktopEntry(String),
+    #[error("invalid plist: {0}")]
+    InvalidPlist(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+pub mod apple_script;
+pub mod backend;
+pub mod command;
+pub mod desktop_entry;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod file;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+pub mod plist;
+pub mod sandbox;
 #[cfg(target_os = "windows")]
 mod windows;
 
@@ -198,10 +242,53 @@ pub struct AutoLaunch {
     /// Args passed to the binary on startup
     pub(crate) args: Vec<String>,
 
+    #[cfg(target_os = "linux")]
+    /// Whether use XDG autostart or systemd for implement
+    pub(crate) launch_mode: LinuxLaunchMode,
+
+    #[cfg(target_os = "linux")]
+    /// Whether run `systemctl --user` for the systemd user service
+    pub(crate) use_systemctl: bool,
+
+    #[cfg(target_os = "linux")]
+    /// Runs the external commands
+    pub(crate) runner: command::SharedRunner,
+
+    #[cfg(target_os = "linux")]
+    /// The optional keys of the desktop entry
+    pub(crate) xdg_options: linux::XdgOptions,
+
     #[cfg(target_os = "macos")]
     /// Whether use Launch Agent for implement or use AppleScript
     pub(crate) use_launch_agent: bool,
 
+    #[cfg(target_os = "macos")]
+    /// Whether the login item is started hidden, only used by AppleScript
+    pub(crate) start_hidden: bool,
+
+    #[cfg(target_os = "macos")]
+    /// The optional keys of the Launch Agent plist
+    pub(crate) launch_agent_options: macos::LaunchAgentOptions,
+
+    #[cfg(not(target_os = "windows"))]
+    /// The dir used in place of the home dir of the current user
+    pub(crate) home_dir: Option<PathBuf>,
+
+    /// The working directory of the launched process
+    pub(crate) working_directory: Option<PathBuf>,
+
+    /// The environment variables of the launched process
+    pub(crate) envs: BTreeMap<String, String>,
+
+    /// How long after login the process is started
+    pub(crate) delay: Option<Duration>,
+
+    /// The root dir which every backend is redirected to, see [`sandbox`]
+    pub(crate) sandbox_root: Option<PathBuf>,
+
+    /// The custom backend used in place of the built-in ones
+    pub(crate) backend: Option<backend::SharedBackend>,
+
     #[cfg(windows)]
     pub(crate) enable_mode: WindowsEnableMode,
 }
@@ -238,6 +325,35 @@ impl AutoLaunch {
     pub fn get_args(&self) -> &[String] {
         &self.args
     }
+
+    /// Get the working directory of the launched process
+    pub fn get_working_directory(&self) -> Option<&Path> {
+        self.working_directory.as_deref()
+    }
+
+    /// Get the environment variables of the launched process
+    pub fn get_envs(&self) -> &BTreeMap<String, String> {
+        &self.envs
+    }
+
+    /// Get how long after login the process is started
+    pub fn get_delay(&self) -> Option<Duration> {
+        self.delay
+    }
+
+    /// Describe where the app is registered by the [`Backend`],
+    /// e.g. the path of the desktop entry
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    pub fn describe(&self) -> String {
+        self.backend().describe(self)
+    }
+
+    /// Get the `delay` in whole seconds, rounded up, `None` if there is no delay
+    pub(crate) fn delay_secs(&self) -> Option<u64> {
+        self.delay
+            .filter(|delay| !delay.is_zero())
+            .map(|delay| delay.as_secs() + u64::from(delay.subsec_nanos() > 0))
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -276,16 +392,249 @@ pub struct AutoLaunchBuilder {
 
     pub use_launch_agent: bool,
 
+    pub linux_launch_mode: LinuxLaunchMode,
+
+    pub use_systemctl: bool,
+
+    pub command_runner: Option<Arc<dyn CommandRunner>>,
+
+    pub backend: Option<Arc<dyn Backend>>,
+
+    pub home_dir: Option<PathBuf>,
+
+    pub sandbox_root: Option<PathBuf>,
+
+    pub working_directory: Option<PathBuf>,
+
+    pub envs: BTreeMap<String, String>,
+
+    pub delay: Option<Duration>,
+
+    pub start_hidden: bool,
+
+    pub hidden_arg: Option<String>,
+
+    pub only_show_in: Option<Vec<String>>,
+
+    pub not_show_in: Option<Vec<String>>,
+
+    pub gnome_autostart_delay: Option<u32>,
+
+    pub gnome_autostart_phase: Option<GnomeAutostartPhase>,
+
+    pub kde_autostart_phase: Option<KdeAutostartPhase>,
+
+    pub display_name: Option<String>,
+
+    pub icon: Option<String>,
+
+    pub comment: Option<String>,
+
+    pub localized_names: BTreeMap<String, String>,
+
+    pub localized_comments: BTreeMap<String, String>,
+
+    pub keep_alive: Option<KeepAlive>,
+
+    pub standard_out_path: Option<PathBuf>,
+
+    pub standard_error_path: Option<PathBuf>,
+
+    pub process_type: Option<ProcessType>,
+
+    pub limit_load_to_session_types: Vec<SessionType>,
+
+    pub throttle_interval: Option<u32>,
+
+    pub associated_bundle_identifiers: Vec<String>,
+
     pub windows_enable_mode: WindowsEnableMode,
 
     pub args: Option<Vec<String>>,
 }
 
-/// Determines how the auto launch is enabled on Windows.
+/// The state of the auto launch, see `AutoLaunch::status`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Enabled with the `app_path` and `args` of the `AutoLaunch`
+    Enabled,
+    /// Not enabled
+    Disabled,
+    /// Registered, but turned off by the user, e.g. in the Task Manager on Windows
+    /// or in the startup applications of GNOME
+    DisabledByUser,
+    /// Enabled, but the entry starts another command, e.g. the path of an old install,
+    /// so it should be rewritten by `AutoLaunch::enable`
+    Stale {
+        /// The command line written by this `AutoLaunch`
+        expected: String,
+        /// The command line found in the entry
+        found: String,
+    },
+    /// Enabled by an entry in another [`Scope`] than the one managed by the `AutoLaunch`,
+    /// e.g. for all users while it manages the entry of the current user
+    RegisteredElsewhere(Scope),
+    /// Enabled, but another registration of the `app_name` also starts the app, so it may
+    /// start twice, e.g. both the XDG autostart entry and the systemd user service on Linux.
+    /// [`AutoLaunch::enable`] resolves it.
+    Conflict,
+}
+
+impl Status {
+    /// Check whether the app is started at login
+    pub fn is_enabled(&self) -> bool {
+        matches!(
+            self,
+            Status::Enabled
+                | Status::Stale { .. }
+                | Status::RegisteredElsewhere(_)
+                | Status::Conflict
+        )
+    }
+}
+
+/// Where an entry is registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// For the current user
+    User,
+    /// For all the users of the system
+    System,
+}
+
+/// Determines how the auto launch is enabled on Linux.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxLaunchMode {
+    /// Writes a desktop entry into the XDG autostart dir, started by the desktop session.
+    #[default]
+    XdgAutostart,
+    /// Writes a systemd user service wanted by `graphical-session.target`,
+    /// restarted on failure and logging into the journal.
+    Systemd,
+}
+
+/// The phase of the GNOME session the desktop entry is started in, `X-GNOME-Autostart-Phase`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GnomeAutostartPhase {
+    EarlyInitialization,
+    PreDisplayServer,
+    DisplayServer,
+    Initialization,
+    WindowManager,
+    Panel,
+    Desktop,
+    /// The phase of the entries without the key.
+    #[default]
+    Applications,
+}
+
+impl GnomeAutostartPhase {
+    /// Get the value of the key
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GnomeAutostartPhase::EarlyInitialization => "EarlyInitialization",
+            GnomeAutostartPhase::PreDisplayServer => "PreDisplayServer",
+            GnomeAutostartPhase::DisplayServer => "DisplayServer",
+            GnomeAutostartPhase::Initialization => "Initialization",
+            GnomeAutostartPhase::WindowManager => "WindowManager",
+            GnomeAutostartPhase::Panel => "Panel",
+            GnomeAutostartPhase::Desktop => "Desktop",
+            GnomeAutostartPhase::Applications => "Applications",
+        }
+    }
+}
+
+/// The phase of the Plasma session the desktop entry is started in, `X-KDE-autostart-phase`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KdeAutostartPhase {
+    /// `0`, with the basic desktop services.
+    BaseDesktop,
+    /// `1`, once the desktop is up.
+    DesktopServices,
+    /// `2`, the phase of the entries without the key.
+    #[default]
+    Applications,
+}
+
+impl KdeAutostartPhase {
+    /// Get the value of the key
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            KdeAutostartPhase::BaseDesktop => 0,
+            KdeAutostartPhase::DesktopServices => 1,
+            KdeAutostartPhase::Applications => 2,
+        }
+    }
+}
+
+/// When launchd restarts the Launch Agent, `KeepAlive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    /// `true`, restarted whenever it exits.
+    Always,
+    /// `SuccessfulExit`, restarted as long as its exit status is zero (`true`)
+    /// or nonzero (`false`).
+    SuccessfulExit(bool),
+    /// `Crashed`, restarted as long as it exits by a signal (`true`) or not (`false`).
+    Crashed(bool),
+}
+
+/// How the resources of the Launch Agent are limited by macOS, `ProcessType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessType {
+    /// Limited as a background job.
+    Background,
+    /// The default limits.
+    Standard,
+    /// Limited as a background job, unless it is doing work for an app.
+    Adaptive,
+    /// Not limited, as the apps of the user.
+    Interactive,
+}
+
+impl ProcessType {
+    /// Get the value of the key
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessType::Background => "Background",
+            ProcessType::Standard => "Standard",
+            ProcessType::Adaptive => "Adaptive",
+            ProcessType::Interactive => "Interactive",
+        }
+    }
+}
+
+/// The kind of login session the Launch Agent is loaded in, `LimitLoadToSessionType`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionType {
+    /// The graphical session of a user.
+    Aqua,
+    /// A session without UI, e.g. over SSH.
+    StandardIO,
+    /// The background session of a user.
+    Background,
+    /// The login window before a user logs in.
+    LoginWindow,
+}
+
+impl SessionType {
+    /// Get the value of the key
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionType::Aqua => "Aqua",
+            SessionType::StandardIO => "StandardIO",
+            SessionType::Background => "Background",
+            SessionType::LoginWindow => "LoginWindow",
+        }
+    }
+}
+
+/// Determines how the auto launch is enabled on Windows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum WindowsEnableMode {
     /// Dynamically tries to enable the auto launch for the system (admin privileges required),
     /// fallbacks to the current user if there is no permission to modify the system registry.
+    #[default]
     Dynamic,
     /// Enables the auto launch for the current user only. Does not require admin permissions.
     CurrentUser,
@@ -293,12 +642,6 @@ pub enum WindowsEnableMode {
     System,
 }
 
-impl Default for WindowsEnableMode {
-    fn default() -> Self {
-        Self::Dynamic
-    }
-}
-
 impl AutoLaunchBuilder {
     pub fn new() -> AutoLaunchBuilder {
         AutoLaunchBuilder::default()
@@ -323,6 +666,245 @@ impl AutoLaunchBuilder {
         self
     }
 
+    /// Set the [`LinuxLaunchMode`].
+    /// This setting only works on Linux
+    pub fn set_linux_launch_mode(&mut self, mode: LinuxLaunchMode) -> &mut Self {
+        self.linux_launch_mode = mode;
+        self
+    }
+
+    /// Set the `use_systemctl`, whether run `systemctl --user` after writing the systemd user service.
+    /// `enable` runs `daemon-reload` and `enable --now`, `disable` runs `disable --now`,
+    /// and `is_enabled` asks `is-enabled`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::Systemd`]
+    pub fn set_use_systemctl(&mut self, use_systemctl: bool) -> &mut Self {
+        self.use_systemctl = use_systemctl;
+        self
+    }
+
+    /// Set the [`CommandRunner`] which runs the external commands,
+    /// defaults to [`SystemCommandRunner`](command::SystemCommandRunner)
+    pub fn set_command_runner(&mut self, runner: Arc<dyn CommandRunner>) -> &mut Self {
+        self.command_runner = Some(runner);
+        self
+    }
+
+    /// Set a custom [`Backend`] used in place of the built-in one of the platform,
+    /// see [`backend`] for details
+    ///
+    /// The options of the builder are passed to it through the getters of [`AutoLaunch`],
+    /// and are not checked against the built-in backends.
+    pub fn set_backend(&mut self, backend: Arc<dyn Backend>) -> &mut Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set the `working_directory` of the launched process.
+    ///
+    /// - Linux: `Path` of the desktop entry, or `WorkingDirectory` of the systemd user service
+    /// - macOS: `WorkingDirectory` of the Launch Agent, not supported by AppleScript
+    /// - Windows: the app is started through `cmd.exe`, which changes to the dir first,
+    ///   see [`AutoLaunchBuilder::set_delay`] for the console window it shows
+    pub fn set_working_directory(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.working_directory = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set an environment variable of the launched process, e.g. `RUST_LOG`.
+    ///
+    /// - Linux: `env` before the `Exec` of the desktop entry,
+    ///   or `Environment` of the systemd user service
+    /// - macOS: `EnvironmentVariables` of the Launch Agent, not supported by AppleScript
+    /// - Windows: the app is started through `cmd.exe`, which sets the variables first,
+    ///   see [`AutoLaunchBuilder::set_delay`] for the console window it shows
+    pub fn set_env(&mut self, key: &str, value: &str) -> &mut Self {
+        self.envs.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the `delay` after login before the app is started, rounded up to whole seconds.
+    ///
+    /// - Linux: the desktop entry starts the app by `/bin/sh` after a `sleep`, since
+    ///   `X-GNOME-Autostart-Delay` is only honored by GNOME. An entry only shown in GNOME
+    ///   ([`AutoLaunchBuilder::set_only_show_in`]) uses `X-GNOME-Autostart-Delay` instead,
+    ///   and the delay of [`AutoLaunchBuilder::set_gnome_autostart_delay`] takes precedence there.
+    ///   The systemd user service has a `sleep` in `ExecStartPre`
+    /// - macOS: launchd has no delay, the app is started by `/bin/sh` after a `sleep`
+    ///   with Launch Agent, not supported by AppleScript
+    /// - Windows: the `Run` key has no delay, unlike the logon trigger of Task Scheduler,
+    ///   so the app is started through `cmd.exe` after a `timeout`
+    ///
+    /// The `cmd.exe` started on Windows, also for `working_directory` and `envs`,
+    /// shows a console window at login, which stays open for the delay and until
+    /// the app is started.
+    pub fn set_delay(&mut self, delay: Duration) -> &mut Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Set the `start_hidden`, whether the app is started without showing its window.
+    ///
+    /// - macOS: the `hidden` property of the login item with AppleScript,
+    ///   the `hidden_arg` is passed with Launch Agent
+    /// - Linux and Windows: the `hidden_arg` is passed after the `args`
+    pub fn set_start_hidden(&mut self, start_hidden: bool) -> &mut Self {
+        self.start_hidden = start_hidden;
+        self
+    }
+
+    /// Set the `hidden_arg` which tells the app to start hidden, defaults to `--hidden`.
+    /// See [`AutoLaunchBuilder::set_start_hidden`]
+    pub fn set_hidden_arg(&mut self, arg: &str) -> &mut Self {
+        self.hidden_arg = Some(arg.into());
+        self
+    }
+
+    /// Set the `only_show_in`, the desktops the entry is only started in, `OnlyShowIn`.
+    /// The names are matched against `$XDG_CURRENT_DESKTOP`, e.g. `GNOME` or `KDE`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_only_show_in(&mut self, desktops: &[impl AsRef<str>]) -> &mut Self {
+        self.only_show_in = Some(desktops.iter().map(|s| s.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Set the `not_show_in`, the desktops the entry is not started in, `NotShowIn`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_not_show_in(&mut self, desktops: &[impl AsRef<str>]) -> &mut Self {
+        self.not_show_in = Some(desktops.iter().map(|s| s.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Set the `gnome_autostart_delay` in seconds, `X-GNOME-Autostart-Delay`,
+    /// which is only honored by GNOME, see [`AutoLaunchBuilder::set_delay`] for the others.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_gnome_autostart_delay(&mut self, seconds: u32) -> &mut Self {
+        self.gnome_autostart_delay = Some(seconds);
+        self
+    }
+
+    /// Set the [`GnomeAutostartPhase`], `X-GNOME-Autostart-Phase`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_gnome_autostart_phase(&mut self, phase: GnomeAutostartPhase) -> &mut Self {
+        self.gnome_autostart_phase = Some(phase);
+        self
+    }
+
+    /// Set the [`KdeAutostartPhase`], `X-KDE-autostart-phase`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_kde_autostart_phase(&mut self, phase: KdeAutostartPhase) -> &mut Self {
+        self.kde_autostart_phase = Some(phase);
+        self
+    }
+
+    /// Set the `display_name`, the `Name` shown by the startup settings of the desktop,
+    /// defaults to the `app_name`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_display_name(&mut self, name: &str) -> &mut Self {
+        self.display_name = Some(name.into());
+        self
+    }
+
+    /// Set the `icon`, an icon name of the icon theme or an absolute path, `Icon`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_icon(&mut self, icon: &str) -> &mut Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// Set the `comment`, defaults to `{app_name} startup script`, `Comment`.
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_comment(&mut self, comment: &str) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Set the name shown for a locale such as `de` or `pt_BR`, `Name[locale]`,
+    /// the locale is checked by [`AutoLaunchBuilder::build`].
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_localized_name(&mut self, locale: &str, name: &str) -> &mut Self {
+        self.localized_names.insert(locale.into(), name.into());
+        self
+    }
+
+    /// Set the comment shown for a locale such as `de` or `pt_BR`, `Comment[locale]`,
+    /// the locale is checked by [`AutoLaunchBuilder::build`].
+    /// This setting only works on Linux with [`LinuxLaunchMode::XdgAutostart`]
+    pub fn set_localized_comment(&mut self, locale: &str, comment: &str) -> &mut Self {
+        self.localized_comments
+            .insert(locale.into(), comment.into());
+        self
+    }
+
+    /// Set the [`KeepAlive`], when launchd restarts the app.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_keep_alive(&mut self, keep_alive: KeepAlive) -> &mut Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Set the file the standard output is appended to, `StandardOutPath`.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_standard_out_path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.standard_out_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the file the standard error is appended to, `StandardErrorPath`.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_standard_error_path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.standard_error_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the [`ProcessType`], e.g. [`ProcessType::Interactive`] for a menu bar app.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_process_type(&mut self, process_type: ProcessType) -> &mut Self {
+        self.process_type = Some(process_type);
+        self
+    }
+
+    /// Set the [`SessionType`]s the Launch Agent is loaded in, e.g. [`SessionType::Aqua`]
+    /// for the graphical session only, `LimitLoadToSessionType`.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_limit_load_to_session_types(&mut self, types: &[SessionType]) -> &mut Self {
+        self.limit_load_to_session_types = types.to_vec();
+        self
+    }
+
+    /// Set the minimum seconds between two starts of the app, `ThrottleInterval`.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_throttle_interval(&mut self, seconds: u32) -> &mut Self {
+        self.throttle_interval = Some(seconds);
+        self
+    }
+
+    /// Set the bundle identifiers of the apps the Launch Agent belongs to,
+    /// shown in the Login Items settings, `AssociatedBundleIdentifiers`.
+    /// This setting only works on macOS with the Launch Agent
+    pub fn set_associated_bundle_identifiers(&mut self, ids: &[impl AsRef<str>]) -> &mut Self {
+        self.associated_bundle_identifiers = ids.iter().map(|s| s.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Set the `home_dir`, which is used in place of the home dir of the current user,
+    /// e.g. for daemons and containers without `HOME`, or for tests.
+    /// On Linux, `$XDG_CONFIG_HOME` is ignored when it is set.
+    /// This setting only works on Linux and macOS
+    pub fn set_home_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.home_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the `sandbox_root`, which every backend is redirected to, so nothing is changed
+    /// on the machine, e.g. for tests.
+    /// The files are written under it as if it were the home dir, with the system dirs
+    /// such as `/etc/xdg` below it, `systemctl` is only run by an explicit [`CommandRunner`],
+    /// and the Windows registry and the macOS login items are kept in memory by [`sandbox`]
+    pub fn set_sandbox_root(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.sandbox_root = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
     /// Set the [`WindowsEnableMode`].
     /// This setting only works on Windows
     pub fn set_windows_enable_mode(&mut self, mode: WindowsEnableMode) -> &mut Self {
@@ -331,6 +913,9 @@ impl AutoLaunchBuilder {
     }
 
     /// Set the args
+    ///
+    /// Each arg reaches the app as is: on Windows, an arg which is empty or contains a space,
+    /// a tab or a quote is quoted, so it should not be quoted beforehand.
     pub fn set_args(&mut self, args: &[impl AsRef<str>]) -> &mut Self {
         self.args = Some(args.iter().map(|s| s.as_ref().to_string()).collect());
         self
@@ -342,28 +927,121 @@ impl AutoLaunchBuilder {
     ///
     /// - `app_name` is none
     /// - `app_path` is none
+    /// - the name of an environment variable is empty or contains `=`
+    /// - a locale of a localized name or comment is not `lang_COUNTRY.ENCODING@MODIFIER`
+    /// - on Linux, `app_path` contains `=` with envs in a desktop entry, which are set by `env`
+    /// - an option is not supported by the backend, e.g. `working_directory` with AppleScript
     /// - Unsupported target OS
     pub fn build(&self) -> Result<AutoLaunch> {
         let app_name = self.app_name.as_ref().ok_or(Error::AppNameNotSpecified)?;
         let app_path = self.app_path.as_ref().ok_or(Error::AppPathNotSpecified)?;
-        let args = self.args.clone().unwrap_or_default();
+        let mut args = self.args.clone().unwrap_or_default();
+        // the login items of AppleScript have the `hidden` property instead
+        let apple_script =
+            cfg!(target_os = "macos") && !self.use_launch_agent && self.backend.is_none();
+        if self.start_hidden && !apple_script {
+            args.push(self.hidden_arg.clone().unwrap_or_else(|| "--hidden".into()));
+        }
+        if let Some(key) = self
+            .envs
+            .keys()
+            .find(|key| key.is_empty() || key.contains(['=', '\0']))
+        {
+            return Err(Error::InvalidEnvName(key.clone()));
+        }
+        if let Some(locale) = self
+            .localized_names
+            .keys()
+            .chain(self.localized_comments.keys())
+            .find(|locale| !desktop_entry::is_valid_locale(locale))
+        {
+            return Err(Error::InvalidLocale(locale.clone()));
+        }
+        // `env` would take the app path for another variable
+        #[cfg(target_os = "linux")]
+        if self.linux_launch_mode == LinuxLaunchMode::XdgAutostart
+            && self.backend.is_none()
+            && !self.envs.is_empty()
+            && app_path.contains('=')
+        {
+            return Err(Error::AppPathContainsEquals(app_path.into()));
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        let home_dir = self.sandbox_root.clone().or_else(|| self.home_dir.clone());
 
         #[cfg(target_os = "linux")]
-        return Ok(AutoLaunch::new(app_name, app_path, &args));
+        return Ok(AutoLaunch {
+            launch_mode: self.linux_launch_mode,
+            // never run the real systemctl in a sandbox
+            use_systemctl: self.use_systemctl
+                && (self.sandbox_root.is_none() || self.command_runner.is_some()),
+            runner: self
+                .command_runner
+                .clone()
+                .map(command::SharedRunner)
+                .unwrap_or_default(),
+            xdg_options: linux::XdgOptions {
+                only_show_in: self.only_show_in.clone(),
+                not_show_in: self.not_show_in.clone(),
+                gnome_autostart_delay: self.gnome_autostart_delay,
+                gnome_autostart_phase: self.gnome_autostart_phase,
+                kde_autostart_phase: self.kde_autostart_phase,
+                display_name: self.display_name.clone(),
+                icon: self.icon.clone(),
+                comment: self.comment.clone(),
+                localized_names: self.localized_names.clone(),
+                localized_comments: self.localized_comments.clone(),
+            },
+            home_dir,
+            working_directory: self.working_directory.clone(),
+            envs: self.envs.clone(),
+            delay: self.delay,
+            sandbox_root: self.sandbox_root.clone(),
+            backend: self.backend.clone().map(backend::SharedBackend),
+            ..AutoLaunch::new(app_name, app_path, &args)
+        });
+        #[cfg(target_os = "macos")]
+        if apple_script && self.working_directory.is_some() {
+            return Err(Error::UnsupportedOption("working_directory"));
+        }
+        #[cfg(target_os = "macos")]
+        if apple_script && !self.envs.is_empty() {
+            return Err(Error::UnsupportedOption("envs"));
+        }
+        #[cfg(target_os = "macos")]
+        if apple_script && self.delay.is_some() {
+            return Err(Error::UnsupportedOption("delay"));
+        }
         #[cfg(target_os = "macos")]
-        return Ok(AutoLaunch::new(
-            app_name,
-            app_path,
-            self.use_launch_agent,
-            &args,
-        ));
+        return Ok(AutoLaunch {
+            home_dir,
+            working_directory: self.working_directory.clone(),
+            envs: self.envs.clone(),
+            delay: self.delay,
+            start_hidden: self.start_hidden && apple_script,
+            launch_agent_options: macos::LaunchAgentOptions {
+                keep_alive: self.keep_alive,
+                standard_out_path: self.standard_out_path.clone(),
+                standard_error_path: self.standard_error_path.clone(),
+                process_type: self.process_type,
+                limit_load_to_session_types: self.limit_load_to_session_types.clone(),
+                throttle_interval: self.throttle_interval,
+                associated_bundle_identifiers: self.associated_bundle_identifiers.clone(),
+            },
+            sandbox_root: self.sandbox_root.clone(),
+            backend: self.backend.clone().map(backend::SharedBackend),
+            ..AutoLaunch::new(app_name, app_path, self.use_launch_agent, &args)
+        });
         #[cfg(target_os = "windows")]
-        return Ok(AutoLaunch::new(
-            app_name,
-            app_path,
-            self.windows_enable_mode,
-            &args,
-        ));
+        return Ok(AutoLaunch {
+            working_directory: self.working_directory.clone(),
+            envs: self.envs.clone(),
+            delay: self.delay,
+            sandbox_root: self.sandbox_root.clone(),
+            backend: self.backend.clone().map(backend::SharedBackend),
+            ..AutoLaunch::new(app_name, app_path, self.windows_enable_mode, &args)
+        });
 
         #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
         return Err(Error::UnsupportedOS);
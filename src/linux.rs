@@ -1,5 +1,35 @@
-use crate::{AutoLaunch, Result};
-use std::{fs, io::Write, path::PathBuf};
+use crate::{
+    backend::{Backend, Systemd, XdgAutostart},
+    command::SharedRunner,
+    desktop_entry::{format_exec, AutostartEntry, DesktopEntry, MAIN_GROUP},
+    file::write_atomic,
+    AutoLaunch, Error, GnomeAutostartPhase, KdeAutostartPhase, LinuxLaunchMode, Result, Scope,
+    Status,
+};
+use std::{collections::BTreeMap, env, fs, io, os::unix::fs::symlink, path::PathBuf};
+
+/// The systemd target which wants the user service
+const SYSTEMD_TARGET: &str = "graphical-session.target";
+
+/// The keys which keep a desktop entry from being started in a session managed by systemd
+const SYSTEMD_SKIP_KEYS: [&str; 2] = ["X-systemd-skip", "X-GNOME-HiddenUnderSystemd"];
+
+/// The optional keys of the desktop entry
+///
+/// The desktop specific keys are left alone when not specified.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct XdgOptions {
+    pub(crate) only_show_in: Option<Vec<String>>,
+    pub(crate) not_show_in: Option<Vec<String>>,
+    pub(crate) gnome_autostart_delay: Option<u32>,
+    pub(crate) gnome_autostart_phase: Option<GnomeAutostartPhase>,
+    pub(crate) kde_autostart_phase: Option<KdeAutostartPhase>,
+    pub(crate) display_name: Option<String>,
+    pub(crate) icon: Option<String>,
+    pub(crate) comment: Option<String>,
+    pub(crate) localized_names: BTreeMap<String, String>,
+    pub(crate) localized_comments: BTreeMap<String, String>,
+}
 
 /// Linux implement
 impl AutoLaunch {
@@ -11,81 +41,781 @@ impl AutoLaunch {
     /// ## Notes
     ///
     /// The parameters of `AutoLaunch::new` are different on each platform.
+    ///
+    /// It uses [`LinuxLaunchMode::XdgAutostart`], see [`AutoLaunchBuilder::set_linux_launch_mode`]
+    /// to use systemd instead.
+    ///
+    /// [`AutoLaunchBuilder::set_linux_launch_mode`]: crate::AutoLaunchBuilder::set_linux_launch_mode
     pub fn new(app_name: &str, app_path: &str, args: &[impl AsRef<str>]) -> AutoLaunch {
         AutoLaunch {
             app_name: app_name.into(),
             app_path: app_path.into(),
             args: args.iter().map(|s| s.as_ref().to_string()).collect(),
+            launch_mode: LinuxLaunchMode::XdgAutostart,
+            use_systemctl: false,
+            runner: SharedRunner::default(),
+            xdg_options: XdgOptions::default(),
+            home_dir: None,
+            working_directory: None,
+            envs: BTreeMap::new(),
+            delay: None,
+            sandbox_root: None,
+            backend: None,
         }
     }
 
     /// Enable the AutoLaunch setting
     ///
+    /// A custom [`Backend`] set with the builder is used in place of the ones below.
+    ///
+    /// #### XDG autostart
+    ///
+    /// The user entry is written even if a system entry exists.
+    /// An existing user entry is merged: only `Type`, `Version`, `Name`, `Comment`, `Exec`,
+    /// `StartupNotify`, `Terminal` and the keys specified with the builder,
+    /// such as `Path`, `Icon` or `OnlyShowIn`, are updated, `Path` and `X-GNOME-Autostart-Delay`
+    /// are removed without a `working_directory` or a delay, the `Hidden=true` override written by
+    /// [`AutoLaunch::disable`] and `X-GNOME-Autostart-enabled=false` are removed,
+    /// and the other keys, groups and comments are kept.
+    /// If the systemd user service of the same `app_name` is enabled, the entry gets
+    /// `X-systemd-skip=true` and `X-GNOME-HiddenUnderSystemd=true` so that it is not started twice.
+    ///
+    /// #### systemd
+    ///
+    /// The user service is written and linked into `graphical-session.target.wants`,
+    /// as `systemctl --user enable` does.
+    /// With `use_systemctl`, `systemctl --user daemon-reload` and `systemctl --user enable --now`
+    /// are run instead of creating the symlink.
+    /// An existing user desktop entry of the same `app_name` gets the skip keys as above,
+    /// and a system one (`$XDG_CONFIG_DIRS/autostart`) is masked by a user copy with them.
+    ///
     /// ## Errors
     ///
-    /// - failed to create dir `~/.config/autostart`
-    /// - failed to create file `~/.config/autostart/{app_name}.desktop`
+    /// - home dir is not found
+    ///
+    /// #### XDG autostart
+    ///
+    /// - failed to read the existing file `$XDG_CONFIG_HOME/autostart/{app_name}.desktop`
+    /// - failed to create dir `$XDG_CONFIG_HOME/autostart`
+    /// - failed to create file `$XDG_CONFIG_HOME/autostart/{app_name}.desktop`
     /// - failed to write bytes to the file
+    ///
+    /// #### systemd
+    ///
+    /// - failed to create dir `$XDG_CONFIG_HOME/systemd/user`
+    /// - failed to create file `$XDG_CONFIG_HOME/systemd/user/{app_name}.service`
+    /// - failed to write bytes to the file
+    /// - failed to create the symlink in `$XDG_CONFIG_HOME/systemd/user/graphical-session.target.wants`
+    /// - failed to execute the `systemctl` command, check the exit status or stderr for details
     pub fn enable(&self) -> Result<()> {
-        let data = format!(
-            "[Desktop Entry]\n\
-            Type=Application\n\
-            Version=1.0\n\
-            Name={}\n\
-            Comment={}startup script\n\
-            Exec={} {}\n\
-            StartupNotify=false\n\
-            Terminal=false",
-            self.app_name,
-            self.app_name,
-            self.app_path,
-            self.args.join(" ")
-        );
-
-        let dir = get_dir();
-        if !dir.exists() {
-            fs::create_dir_all(&dir).or_else(|e| {
-                if e.kind() == std::io::ErrorKind::AlreadyExists {
-                    Ok(())
-                } else {
-                    Err(e)
-                }
-            })?;
-        }
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(self.get_file())?;
-        file.write_all(data.as_bytes())?;
-        Ok(())
+        self.backend().enable(self)
+    }
+
+    fn enable_xdg(&self) -> Result<()> {
+        write_atomic(
+            &self.get_file()?,
+            self.get_entry(false)?.to_string().as_bytes(),
+        )
+    }
+
+    fn enable_systemd(&self) -> Result<()> {
+        let unit = self.get_unit_file()?;
+        write_atomic(&unit, self.get_unit().as_bytes())?;
+
+        if self.use_systemctl {
+            self.exec_systemctl(&["daemon-reload"])?;
+            self.exec_systemctl(&["enable", "--now", &self.unit_name()])?;
+        } else {
+            let link = self.get_unit_link()?;
+            fs::create_dir_all(self.get_unit_wants_dir()?)?;
+            if link.symlink_metadata().is_ok() {
+                fs::remove_file(&link)?;
+            }
+            symlink(unit, link)?;
+        }
+        self.set_xdg_skip(true)
     }
 
     /// Disable the AutoLaunch setting
     ///
+    /// A custom [`Backend`] set with the builder is used in place of the ones below.
+    ///
+    /// #### XDG autostart
+    ///
+    /// When a system entry (`$XDG_CONFIG_DIRS/autostart/{app_name}.desktop`) exists,
+    /// the user entry is overwritten with `Hidden=true` to mask it, as the XDG Autostart spec defines.
+    /// Otherwise the user entry is removed.
+    ///
+    /// #### systemd
+    ///
+    /// The user service and its symlink are removed.
+    /// With `use_systemctl`, the service is also stopped by `systemctl --user disable --now`
+    /// and `systemctl --user daemon-reload` is run at last.
+    /// The skip keys are removed from the user desktop entry of the same `app_name`,
+    /// and the user copy of a system entry is removed if it was left unchanged.
+    ///
     /// ## Errors
     ///
-    /// - failed to remove file `~/.config/autostart/{app_name}.desktop`
+    /// - home dir is not found
+    ///
+    /// #### XDG autostart
+    ///
+    /// - failed to remove file `$XDG_CONFIG_HOME/autostart/{app_name}.desktop`
+    /// - failed to write the `Hidden=true` override
+    ///
+    /// #### systemd
+    ///
+    /// - failed to remove file `$XDG_CONFIG_HOME/systemd/user/{app_name}.service` or its symlink
+    /// - failed to execute the `systemctl` command, check the exit status or stderr for details
     pub fn disable(&self) -> Result<()> {
-        let file = self.get_file();
+        self.backend().disable(self)
+    }
+
+    fn disable_xdg(&self) -> Result<()> {
+        if self.find_system_file().is_some() {
+            let entry = self.get_entry(true)?;
+            return write_atomic(&self.get_file()?, entry.to_string().as_bytes());
+        }
+
+        let file = self.get_file()?;
         if file.exists() {
             fs::remove_file(file)?;
         }
         Ok(())
     }
 
+    fn disable_systemd(&self) -> Result<()> {
+        let unit = self.get_unit_file()?;
+        if self.use_systemctl && unit.exists() {
+            self.exec_systemctl(&["disable", "--now", &self.unit_name()])?;
+        }
+
+        let link = self.get_unit_link()?;
+        if link.symlink_metadata().is_ok() {
+            fs::remove_file(link)?;
+        }
+        if unit.exists() {
+            fs::remove_file(unit)?;
+        }
+
+        if self.use_systemctl {
+            self.exec_systemctl(&["daemon-reload"])?;
+        }
+        self.set_xdg_skip(false)
+    }
+
     /// Check whether the AutoLaunch setting is enabled
+    ///
+    /// #### XDG autostart
+    ///
+    /// The entry may be found in the user autostart dir or in any of the system ones
+    /// (`$XDG_CONFIG_DIRS/autostart`), the user one masks the others.
+    /// It is not enabled if the entry is `Hidden`, has `X-GNOME-Autostart-enabled=false`,
+    /// or is not shown in the `$XDG_CURRENT_DESKTOP` by `OnlyShowIn`/`NotShowIn`.
+    ///
+    /// #### systemd
+    ///
+    /// The user service exists and is linked into `graphical-session.target.wants`.
+    /// With `use_systemctl`, `systemctl --user is-enabled` is asked instead.
+    ///
+    /// ## Errors
+    ///
+    /// - home dir is not found
+    /// - failed to read the desktop entry file
+    /// - failed to spawn the `systemctl` command
     pub fn is_enabled(&self) -> Result<bool> {
-        Ok(self.get_file().exists())
+        Ok(self.status()?.is_enabled())
+    }
+
+    /// Get the [`Status`] of the AutoLaunch setting
+    ///
+    /// It is enabled as [`AutoLaunch::is_enabled`] checks, and [`Status::Stale`] if the `Exec`
+    /// of the desktop entry or the `ExecStart` of the systemd user service starts another
+    /// program or args than the `app_path` and `args`.
+    ///
+    /// #### XDG autostart
+    ///
+    /// An entry turned off in the startup applications of GNOME (`X-GNOME-Autostart-enabled=false`)
+    /// is [`Status::DisabledByUser`], and an entry only found in the system autostart dirs
+    /// is [`Status::RegisteredElsewhere`].
+    ///
+    /// #### systemd
+    ///
+    /// A user service which exists but is not enabled, e.g. by `systemctl --user disable`,
+    /// is [`Status::DisabledByUser`].
+    ///
+    /// #### Both
+    ///
+    /// When the entry and the user service of the `app_name` would both start the app,
+    /// as [`AutoLaunch::has_conflict`] checks, either backend reports [`Status::Conflict`].
+    ///
+    /// ## Errors
+    ///
+    /// - home dir is not found
+    /// - failed to read the desktop entry file
+    /// - failed to spawn the `systemctl` command
+    pub fn status(&self) -> Result<Status> {
+        self.backend().status(self)
+    }
+
+    /// Get the custom [`Backend`], or the built-in one of the `launch_mode`
+    pub(crate) fn backend(&self) -> &dyn Backend {
+        match (&self.backend, self.launch_mode) {
+            (Some(backend), _) => backend.0.as_ref(),
+            (None, LinuxLaunchMode::XdgAutostart) => &XdgAutostart,
+            (None, LinuxLaunchMode::Systemd) => &Systemd,
+        }
+    }
+
+    fn xdg_status(&self) -> Result<Status> {
+        let file = self.get_file()?;
+        let (file, scope) = if file.exists() {
+            (file, Scope::User)
+        } else {
+            match self.find_system_file() {
+                Some(file) => (file, Scope::System),
+                None => return Ok(Status::Disabled),
+            }
+        };
+
+        let entry = AutostartEntry::parse(&fs::read_to_string(file)?);
+        if !entry.hidden && entry.gnome_autostart_enabled == Some(false) {
+            return Ok(Status::DisabledByUser);
+        }
+        if !entry.is_enabled_in(&get_current_desktops()) {
+            return Ok(Status::Disabled);
+        }
+        if scope == Scope::System {
+            return Ok(Status::RegisteredElsewhere(Scope::System));
+        }
+
+        let expected = self.get_exec_args();
+        match entry.args() {
+            Ok(args) if args == expected => Ok(Status::Enabled),
+            _ => Ok(Status::Stale {
+                expected: format_exec(&expected[0], &expected[1..]),
+                found: entry.exec.unwrap_or_default(),
+            }),
+        }
+    }
+
+    fn systemd_status(&self) -> Result<Status> {
+        if !self.is_systemd_enabled()? {
+            if self.get_unit_file()?.exists() {
+                return Ok(Status::DisabledByUser);
+            }
+            return Ok(Status::Disabled);
+        }
+
+        let expected = format_unit_exec(&self.app_path, &self.args);
+        // with `use_systemctl`, the enabled unit may live in another dir
+        let found = match fs::read_to_string(self.get_unit_file()?) {
+            Ok(unit) => DesktopEntry::parse(&unit)
+                .get_raw("Service", "ExecStart")
+                .unwrap_or_default()
+                .to_string(),
+            Err(_) => String::new(),
+        };
+        if found == expected {
+            Ok(Status::Enabled)
+        } else {
+            Ok(Status::Stale { expected, found })
+        }
+    }
+
+    /// Check whether both the XDG autostart entry and the systemd user service of the `app_name`
+    /// would start the app
+    ///
+    /// The entry is not started in a session managed by systemd if it has `X-systemd-skip=true`
+    /// or `X-GNOME-HiddenUnderSystemd=true`, which [`AutoLaunch::enable`] writes when both exist.
+    /// [`AutoLaunch::status`] reports a conflict as [`Status::Conflict`].
+    ///
+    /// ## Errors
+    ///
+    /// - failed to read the desktop entry file
+    /// - failed to spawn the `systemctl` command
+    pub fn has_conflict(&self) -> Result<bool> {
+        Ok(self.is_systemd_enabled()? && self.is_xdg_started_under_systemd()?)
+    }
+
+    /// Check whether the desktop entry is started, even in a session managed by systemd
+    fn is_xdg_started_under_systemd(&self) -> Result<bool> {
+        match self.find_file()? {
+            Some(file) => {
+                let entry = DesktopEntry::parse(&fs::read_to_string(file)?);
+                let skipped = SYSTEMD_SKIP_KEYS
+                    .iter()
+                    .any(|key| entry.get_bool(key) == Some(true));
+                Ok(!skipped && AutostartEntry::from(&entry).is_enabled_in(&get_current_desktops()))
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Add or remove the skip keys of the user desktop entry
+    ///
+    /// When only a system entry exists, it is copied into a user entry with the skip keys,
+    /// which masks it. The copy is removed with the skip keys, if it was left unchanged.
+    fn set_xdg_skip(&self, skip: bool) -> Result<()> {
+        let file = self.get_file()?;
+        let system_file = self.find_system_file();
+        let text = match &system_file {
+            _ if file.exists() => fs::read_to_string(&file)?,
+            Some(system_file) if skip => fs::read_to_string(system_file)?,
+            _ => return Ok(()),
+        };
+        let mut entry = DesktopEntry::parse(&text);
+        for key in SYSTEMD_SKIP_KEYS {
+            if skip {
+                entry.set_bool(key, true);
+            } else {
+                entry.remove(MAIN_GROUP, key);
+            }
+        }
+
+        if let Some(system_file) = system_file.filter(|_| !skip) {
+            if fs::read_to_string(system_file)? == entry.to_string() {
+                fs::remove_file(file)?;
+                return Ok(());
+            }
+        }
+        write_atomic(&file, entry.to_string().as_bytes())
+    }
+
+    fn is_systemd_enabled(&self) -> Result<bool> {
+        if !self.use_systemctl {
+            return Ok(self.get_unit_link()?.exists());
+        }
+
+        // exits with a failure status unless it is enabled, so only the state is checked
+        let output = self
+            .runner
+            .0
+            .run("systemctl", &["--user", "is-enabled", &self.unit_name()])?;
+        let stdout = std::str::from_utf8(&output.stdout).unwrap_or("");
+        Ok(matches!(stdout.trim(), "enabled" | "enabled-runtime"))
+    }
+
+    /// Execute `systemctl --user` with the args
+    fn exec_systemctl(&self, args: &[&str]) -> Result<()> {
+        let mut user_args = vec!["--user"];
+        user_args.extend_from_slice(args);
+        let output = self.runner.0.run("systemctl", &user_args)?;
+        if !output.status.success() {
+            return Err(Error::SystemctlFailed(output.status.code().unwrap_or(1)));
+        }
+        Ok(())
+    }
+
+    /// Get the desktop entry, merged into the existing user entry
+    ///
+    /// Only the keys owned by this crate are updated, the other keys, groups and comments
+    /// added by the user or the desktop are kept in place.
+    fn get_entry(&self, hidden: bool) -> Result<DesktopEntry> {
+        let mut entry = match fs::read_to_string(self.get_file()?) {
+            Ok(text) => DesktopEntry::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DesktopEntry::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        entry.set_string("Type", "Application");
+        entry.set_string("Version", "1.0");
+        let options = &self.xdg_options;
+        let name = options.display_name.as_deref().unwrap_or(&self.app_name);
+        entry.set_string("Name", name);
+        match &options.comment {
+            Some(comment) => entry.set_string("Comment", comment),
+            None => entry.set_string("Comment", &format!("{} startup script", self.app_name)),
+        }
+        let exec = self.get_exec_args();
+        entry.set_string("Exec", &format_exec(&exec[0], &exec[1..]));
+        entry.set_bool("StartupNotify", false);
+        entry.set_bool("Terminal", false);
+        match &self.working_directory {
+            Some(dir) => entry.set_string("Path", &dir.to_string_lossy()),
+            None => entry.remove(MAIN_GROUP, "Path"),
+        }
+        if let Some(icon) = &options.icon {
+            entry.set_string("Icon", icon);
+        }
+        for (locale, name) in &options.localized_names {
+            entry.set_string(&format!("Name[{}]", locale), name);
+        }
+        for (locale, comment) in &options.localized_comments {
+            entry.set_string(&format!("Comment[{}]", locale), comment);
+        }
+        for (key, desktops) in [
+            ("OnlyShowIn", &options.only_show_in),
+            ("NotShowIn", &options.not_show_in),
+        ] {
+            match desktops {
+                Some(desktops) if desktops.is_empty() => entry.remove(MAIN_GROUP, key),
+                Some(desktops) => entry.set_strings(key, desktops),
+                None => {}
+            }
+        }
+        let gnome_delay = options
+            .gnome_autostart_delay
+            .map(u64::from)
+            .or(self.delay_secs().filter(|_| self.is_gnome_only()));
+        match gnome_delay {
+            Some(delay) => entry.set_string("X-GNOME-Autostart-Delay", &delay.to_string()),
+            None => entry.remove(MAIN_GROUP, "X-GNOME-Autostart-Delay"),
+        }
+        if let Some(phase) = options.gnome_autostart_phase {
+            entry.set_string("X-GNOME-Autostart-Phase", phase.as_str());
+        }
+        if let Some(phase) = options.kde_autostart_phase {
+            entry.set_string("X-KDE-autostart-phase", &phase.as_u32().to_string());
+        }
+
+        if hidden {
+            entry.set_bool("Hidden", true);
+        } else {
+            entry.remove(MAIN_GROUP, "Hidden");
+            // turned off in the startup settings of GNOME
+            if entry.get_bool("X-GNOME-Autostart-enabled") == Some(false) {
+                entry.remove(MAIN_GROUP, "X-GNOME-Autostart-enabled");
+            }
+        }
+
+        // the skip keys written with the service are removed by `disable_systemd`,
+        // the ones added by the user or the distro for another unit are kept
+        if self.get_unit_link()?.exists() {
+            for key in SYSTEMD_SKIP_KEYS {
+                entry.set_bool(key, true);
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Get the program and its args of the `Exec`, run by `env` to set the `envs`,
+    /// and by `/bin/sh` to wait for the `delay`
+    ///
+    /// `--` keeps `env` from reading an app path starting with `-` as an option.
+    fn get_exec_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.envs.is_empty() {
+            args.push("env".to_string());
+            args.push("--".to_string());
+            args.extend(self.envs.iter().map(|(k, v)| format!("{}={}", k, v)));
+        }
+        if let Some(delay) = self.delay_secs().filter(|_| !self.is_gnome_only()) {
+            // only GNOME honors `X-GNOME-Autostart-Delay`, so the shell sleeps
+            // and then replaces itself with the app, as with the Launch Agent of macOS
+            args.push("/bin/sh".to_string());
+            args.push("-c".to_string());
+            args.push(format!("sleep {}; exec \"$0\" \"$@\"", delay));
+        }
+        args.push(self.app_path.clone());
+        args.extend_from_slice(&self.args);
+        args
+    }
+
+    /// Check whether the entry is only shown in GNOME by `OnlyShowIn`
+    fn is_gnome_only(&self) -> bool {
+        self.xdg_options
+            .only_show_in
+            .as_ref()
+            .is_some_and(|desktops| !desktops.is_empty() && desktops.iter().all(|d| d == "GNOME"))
+    }
+
+    /// Get the autostart dir, `$XDG_CONFIG_HOME/autostart`
+    fn get_dir(&self) -> Result<PathBuf> {
+        Ok(self.get_config_home()?.join("autostart"))
+    }
+
+    /// Get the systemd user service dir, `$XDG_CONFIG_HOME/systemd/user`
+    fn get_unit_dir(&self) -> Result<PathBuf> {
+        Ok(self.get_config_home()?.join("systemd").join("user"))
+    }
+
+    /// Get the dir of the units wanted by the systemd target
+    fn get_unit_wants_dir(&self) -> Result<PathBuf> {
+        Ok(self
+            .get_unit_dir()?
+            .join(format!("{}.wants", SYSTEMD_TARGET)))
+    }
+
+    /// Get `$XDG_CONFIG_HOME`, defaults to `~/.config`
+    ///
+    /// `$XDG_CONFIG_HOME` is ignored if the `home_dir` is specified.
+    fn get_config_home(&self) -> Result<PathBuf> {
+        if let Some(home) = &self.home_dir {
+            return Ok(home.join(".config"));
+        }
+        if let Some(dir) = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            // relative paths are invalid and should be ignored
+            .filter(|dir| dir.is_absolute())
+        {
+            return Ok(dir);
+        }
+        let home = dirs::home_dir().ok_or(Error::HomeDirNotFound)?;
+        Ok(home.join(".config"))
     }
 
     /// Get the desktop entry file path
-    fn get_file(&self) -> PathBuf {
-        get_dir().join(format!("{}.desktop", self.app_name))
+    fn get_file(&self) -> Result<PathBuf> {
+        Ok(self.get_dir()?.join(self.file_name()))
+    }
+
+    /// Get the desktop entry file which takes effect, the user one comes first
+    fn find_file(&self) -> Result<Option<PathBuf>> {
+        Ok(Some(self.get_file()?)
+            .filter(|file| file.exists())
+            .or_else(|| self.find_system_file()))
+    }
+
+    /// Get the system desktop entry file which takes effect
+    fn find_system_file(&self) -> Option<PathBuf> {
+        self.get_system_dirs()
+            .into_iter()
+            .map(|dir| dir.join(self.file_name()))
+            .find(|file| file.exists())
+    }
+
+    /// Get the system autostart dirs, `$XDG_CONFIG_DIRS/autostart`, in order of preference
+    ///
+    /// It is only `{sandbox_root}/etc/xdg/autostart` in a sandbox.
+    fn get_system_dirs(&self) -> Vec<PathBuf> {
+        let dirs = match &self.sandbox_root {
+            Some(root) => vec![root.join("etc").join("xdg")],
+            None => get_config_dirs(),
+        };
+        dirs.into_iter().map(|dir| dir.join("autostart")).collect()
+    }
+
+    /// Get the desktop entry file name
+    fn file_name(&self) -> String {
+        format!("{}.desktop", self.app_name)
+    }
+
+    /// Get the content of the systemd user service
+    fn get_unit(&self) -> String {
+        let mut service = String::new();
+        if let Some(delay) = self.delay_secs() {
+            // the delay counts as starting
+            service.push_str(&format!(
+                "ExecStartPre=/bin/sleep {}\nTimeoutStartSec=infinity\n",
+                delay
+            ));
+        }
+        service.push_str(&format!(
+            "ExecStart={}\n",
+            format_unit_exec(&self.app_path, &self.args)
+        ));
+        if let Some(dir) = &self.working_directory {
+            let mut dir = dir.to_string_lossy().into_owned();
+            // a doubled `\` would be part of the path, while a trailing `/` is ignored
+            if dir.ends_with('\\') {
+                dir.push('/');
+            }
+            service.push_str(&format!("WorkingDirectory={}\n", escape_unit_value(&dir)));
+        }
+        for (key, value) in &self.envs {
+            service.push_str(&format!("Environment={}\n", quote_unit_env(key, value)));
+        }
+
+        format!(
+            "[Unit]\n\
+            Description={}\n\
+            PartOf={}\n\
+            After={}\n\
+            \n\
+            [Service]\n\
+            {}\
+            Restart=on-failure\n\
+            StandardOutput=journal\n\
+            StandardError=journal\n\
+            \n\
+            [Install]\n\
+            WantedBy={}\n",
+            escape_unit_value(&self.app_name),
+            SYSTEMD_TARGET,
+            SYSTEMD_TARGET,
+            service,
+            SYSTEMD_TARGET,
+        )
+    }
+
+    /// Get the systemd user service file path
+    fn get_unit_file(&self) -> Result<PathBuf> {
+        Ok(self.get_unit_dir()?.join(self.unit_name()))
+    }
+
+    /// Get the symlink which makes the target want the systemd user service
+    fn get_unit_link(&self) -> Result<PathBuf> {
+        Ok(self.get_unit_wants_dir()?.join(self.unit_name()))
+    }
+
+    /// Get the systemd user service name, `{app_name}.service`
+    ///
+    /// The characters which are invalid in a unit name are escaped as `systemd-escape` does,
+    /// and `/` becomes `-`. The rest, including `-`, is kept so the unit is named as the app.
+    fn unit_name(&self) -> String {
+        let mut name = String::new();
+        for (i, byte) in self.app_name.bytes().enumerate() {
+            match byte {
+                b'/' => name.push('-'),
+                b'.' if i == 0 => name.push_str("\\x2e"),
+                b if b.is_ascii_alphanumeric() || b":_.-".contains(&b) => name.push(b as char),
+                b => name.push_str(&format!("\\x{:02x}", b)),
+            }
+        }
+        name + ".service"
     }
 }
 
-/// Get the autostart dir
-fn get_dir() -> PathBuf {
-    dirs::home_dir().unwrap().join(".config").join("autostart")
+impl Backend for XdgAutostart {
+    fn enable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.enable_xdg()
+    }
+
+    fn disable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.disable_xdg()
+    }
+
+    fn status(&self, auto: &AutoLaunch) -> Result<Status> {
+        let status = auto.xdg_status()?;
+        if status.is_enabled() && auto.has_conflict()? {
+            return Ok(Status::Conflict);
+        }
+        Ok(status)
+    }
+
+    fn describe(&self, auto: &AutoLaunch) -> String {
+        let file = match auto.get_file() {
+            Ok(file) => file.display().to_string(),
+            Err(_) => format!("$XDG_CONFIG_HOME/autostart/{}", auto.file_name()),
+        };
+        format!("XDG autostart entry {}", file)
+    }
+}
+
+impl Backend for Systemd {
+    fn enable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.enable_systemd()
+    }
+
+    fn disable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.disable_systemd()
+    }
+
+    fn status(&self, auto: &AutoLaunch) -> Result<Status> {
+        let status = auto.systemd_status()?;
+        // the service is enabled, so only the entry is checked
+        if status.is_enabled() && auto.is_xdg_started_under_systemd()? {
+            return Ok(Status::Conflict);
+        }
+        Ok(status)
+    }
+
+    fn describe(&self, auto: &AutoLaunch) -> String {
+        let file = match auto.get_unit_file() {
+            Ok(file) => file.display().to_string(),
+            Err(_) => format!("$XDG_CONFIG_HOME/systemd/user/{}", auto.unit_name()),
+        };
+        format!("systemd user service {}", file)
+    }
+}
+
+/// Build the value of `ExecStart` from a program and its args
+fn format_unit_exec(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .map(quote_unit_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote a single word of a systemd command line
+///
+/// `%` and `$` are doubled so that specifiers and environment variables are not expanded.
+fn quote_unit_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "\"'\\;".contains(c));
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    if needs_quotes {
+        quoted.push('"');
+    }
+    for c in arg.chars() {
+        match c {
+            '%' => quoted.push_str("%%"),
+            '$' => quoted.push_str("$$"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    if needs_quotes {
+        quoted.push('"');
+    }
+    quoted
+}
+
+/// Quote an assignment of `Environment`, `$` is not expanded there
+fn quote_unit_env(key: &str, value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in key.chars().chain(std::iter::once('=')).chain(value.chars()) {
+        match c {
+            '%' => quoted.push_str("%%"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Escape a plain value of a systemd unit, `%` specifiers are not expanded
+///
+/// A line ending with an odd number of `\`, once the trailing whitespace is stripped,
+/// is continued on the next line, so another `\` is added in that case.
+fn escape_unit_value(value: &str) -> String {
+    let mut escaped = value
+        .replace('%', "%%")
+        .replace(|c: char| c.is_control(), " ")
+        .trim_end()
+        .to_string();
+    let backslashes = escaped.len() - escaped.trim_end_matches('\\').len();
+    if backslashes % 2 == 1 {
+        escaped.push('\\');
+    }
+    escaped
+}
+
+/// Get the names of the current desktop environment from `$XDG_CURRENT_DESKTOP`
+fn get_current_desktops() -> Vec<String> {
+    env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Get `$XDG_CONFIG_DIRS`, defaults to `/etc/xdg`
+fn get_config_dirs() -> Vec<PathBuf> {
+    let dirs = env::var_os("XDG_CONFIG_DIRS")
+        .map(|dirs| {
+            env::split_paths(&dirs)
+                .filter(|dir| dir.is_absolute())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if dirs.is_empty() {
+        vec![PathBuf::from("/etc/xdg")]
+    } else {
+        dirs
+    }
 }
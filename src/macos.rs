@@ -1,9 +1,28 @@
-use crate::{AutoLaunch, Error, Result};
+use crate::{
+    apple_script::{parse_string_list, LoginItemCommand},
+    backend::{AppleScript, Backend, LaunchAgent},
+    file::write_atomic,
+    plist::{self, Dictionary, Value},
+    sandbox, AutoLaunch, Error, KeepAlive, ProcessType, Result, SessionType, Status,
+};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Write;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
 
+/// The optional keys of the Launch Agent plist
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct LaunchAgentOptions {
+    pub(crate) keep_alive: Option<KeepAlive>,
+    pub(crate) standard_out_path: Option<PathBuf>,
+    pub(crate) standard_error_path: Option<PathBuf>,
+    pub(crate) process_type: Option<ProcessType>,
+    pub(crate) limit_load_to_session_types: Vec<SessionType>,
+    pub(crate) throttle_interval: Option<u32>,
+    pub(crate) associated_bundle_identifiers: Vec<String>,
+}
+
 /// macOS implement
 impl AutoLaunch {
     /// Create a new AutoLaunch instance
@@ -23,7 +42,9 @@ impl AutoLaunch {
     ///     otherwise it will cause an error when `enable`.
     ///
     /// In case using AppleScript (`use_launch_agent=false`),
-    ///     only `"--hidden"` and `"--minimized"` in `args` are valid.
+    ///     the `args` are not passed to the app, see
+    ///     [`AutoLaunchBuilder::set_start_hidden`](crate::AutoLaunchBuilder::set_start_hidden)
+    ///     to start it hidden.
     pub fn new(
         app_name: &str,
         app_path: &str,
@@ -47,12 +68,22 @@ impl AutoLaunch {
             app_name: name.into(),
             app_path: app_path.into(),
             use_launch_agent,
+            start_hidden: false,
+            launch_agent_options: LaunchAgentOptions::default(),
             args: args.iter().map(|s| s.as_ref().to_string()).collect(),
+            home_dir: None,
+            working_directory: None,
+            envs: BTreeMap::new(),
+            delay: None,
+            sandbox_root: None,
+            backend: None,
         }
     }
 
     /// Enable the AutoLaunch setting
     ///
+    /// A custom [`Backend`] set with the builder is used in place of the ones below.
+    ///
     /// ## Errors
     ///
     /// - `app_path` does not exist
@@ -60,6 +91,7 @@ impl AutoLaunch {
     ///
     /// #### Launch Agent
     ///
+    /// - home dir is not found
     /// - failed to create dir `~/Library/LaunchAgents`
     /// - failed to create file `~/Library/LaunchAgents/{app_name}.plist`
     /// - failed to write bytes to the file
@@ -68,6 +100,11 @@ impl AutoLaunch {
     ///
     /// - failed to execute the `osascript` command, check the exit status or stderr for details
     pub fn enable(&self) -> Result<()> {
+        self.backend().enable(self)
+    }
+
+    /// Check that the `app_path` exists and is absolute
+    fn check_app_path(&self) -> Result<()> {
         let path = Path::new(&self.app_path);
 
         if !path.exists() {
@@ -77,53 +114,107 @@ impl AutoLaunch {
         if !path.is_absolute() {
             return Err(Error::AppPathIsNotAbsolute(path.to_path_buf()));
         }
+        Ok(())
+    }
+
+    fn enable_launch_agent(&self) -> Result<()> {
+        self.check_app_path()?;
+        let dir = self.get_dir()?;
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
 
-        if self.use_launch_agent {
-            let dir = get_dir();
-            if !dir.exists() {
-                fs::create_dir(&dir)?;
+        let mut plist = Dictionary::new();
+        plist.insert("Label", self.app_name.as_str());
+        plist.insert(
+            "ProgramArguments",
+            self.get_program_args()
+                .into_iter()
+                .map(Value::from)
+                .collect::<Vec<_>>(),
+        );
+        plist.insert("RunAtLoad", true);
+        if let Some(dir) = &self.working_directory {
+            plist.insert("WorkingDirectory", dir.to_string_lossy().into_owned());
+        }
+        if !self.envs.is_empty() {
+            let mut vars = Dictionary::new();
+            for (key, value) in &self.envs {
+                vars.insert(key, value.as_str());
             }
+            plist.insert("EnvironmentVariables", vars);
+        }
 
-            let mut args = vec![self.app_path.clone()];
-            args.extend_from_slice(&self.args);
-
-            let section = args
-                .iter()
-                .map(|x| format!("<string>{}</string>", x))
-                .collect::<String>();
-
-            let data = format!(
-                "{}\n{}\n\
-            <plist version=\"1.0\">\n  \
-            <dict>\n  \
-                <key>Label</key>\n  \
-                <string>{}</string>\n  \
-                <key>ProgramArguments</key>\n  \
-                <array>{}</array>\n  \
-                <key>RunAtLoad</key>\n  \
-                <true/>\n  \
-            </dict>\n\
-            </plist>",
-                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
-                r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#,
-                self.app_name,
-                section
+        let options = &self.launch_agent_options;
+        match options.keep_alive {
+            Some(KeepAlive::Always) => plist.insert("KeepAlive", true),
+            Some(KeepAlive::SuccessfulExit(exit)) => {
+                let mut conditions = Dictionary::new();
+                conditions.insert("SuccessfulExit", exit);
+                plist.insert("KeepAlive", conditions);
+            }
+            Some(KeepAlive::Crashed(crashed)) => {
+                let mut conditions = Dictionary::new();
+                conditions.insert("Crashed", crashed);
+                plist.insert("KeepAlive", conditions);
+            }
+            None => {}
+        }
+        for (key, path) in [
+            ("StandardOutPath", &options.standard_out_path),
+            ("StandardErrorPath", &options.standard_error_path),
+        ] {
+            if let Some(path) = path {
+                plist.insert(key, path.to_string_lossy().into_owned());
+            }
+        }
+        if let Some(process_type) = options.process_type {
+            plist.insert("ProcessType", process_type.as_str());
+        }
+        match options.limit_load_to_session_types.as_slice() {
+            [] => {}
+            [session_type] => plist.insert("LimitLoadToSessionType", session_type.as_str()),
+            session_types => plist.insert(
+                "LimitLoadToSessionType",
+                session_types
+                    .iter()
+                    .map(|session_type| Value::from(session_type.as_str()))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+        if let Some(seconds) = options.throttle_interval {
+            plist.insert("ThrottleInterval", i64::from(seconds));
+        }
+        if !options.associated_bundle_identifiers.is_empty() {
+            plist.insert(
+                "AssociatedBundleIdentifiers",
+                options
+                    .associated_bundle_identifiers
+                    .iter()
+                    .map(|id| Value::from(id.as_str()))
+                    .collect::<Vec<_>>(),
             );
-            fs::File::create(self.get_file())?.write(data.as_bytes())?;
-        } else {
-            let hidden = self
-                .args
-                .iter()
-                .find(|arg| *arg == "--hidden" || *arg == "--minimized");
-
-            let props = format!(
-                "{{name:\"{}\",path:\"{}\",hidden:{}}}",
-                self.app_name,
-                self.app_path,
-                hidden.is_some()
+        }
+
+        let data = Value::from(plist).to_xml();
+        write_atomic(&self.get_file()?, data.as_bytes())
+    }
+
+    fn enable_login_item(&self) -> Result<()> {
+        self.check_app_path()?;
+        if let Some(root) = &self.sandbox_root {
+            sandbox::set_value(
+                root,
+                sandbox::LOGIN_ITEMS_KEY,
+                &self.app_name,
+                self.app_path.as_bytes(),
             );
-            let command = format!("make login item at end with properties {}", props);
-            let output = exec_apple_script(&command)?;
+        } else {
+            let output = exec_apple_script(LoginItemCommand::Make {
+                name: &self.app_name,
+                path: &self.app_path,
+                hidden: self.start_hidden,
+            })?;
             if !output.status.success() {
                 return Err(Error::AppleScriptFailed(output.status.code().unwrap_or(1)));
             }
@@ -133,6 +224,8 @@ impl AutoLaunch {
 
     /// Disable the AutoLaunch setting
     ///
+    /// A custom [`Backend`] set with the builder is used in place of the ones below.
+    ///
     /// ## Errors
     ///
     /// #### Launch Agent
@@ -143,14 +236,24 @@ impl AutoLaunch {
     ///
     /// - failed to execute the `osascript` command, check the exit status or stderr for details
     pub fn disable(&self) -> Result<()> {
-        if self.use_launch_agent {
-            let file = self.get_file();
-            if file.exists() {
-                fs::remove_file(file)?;
-            }
+        self.backend().disable(self)
+    }
+
+    fn disable_launch_agent(&self) -> Result<()> {
+        let file = self.get_file()?;
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+
+    fn disable_login_item(&self) -> Result<()> {
+        if let Some(root) = &self.sandbox_root {
+            sandbox::remove_value(root, sandbox::LOGIN_ITEMS_KEY, &self.app_name);
         } else {
-            let command = format!("delete login item \"{}\"", self.app_name);
-            let output = exec_apple_script(&command)?;
+            let output = exec_apple_script(LoginItemCommand::Delete {
+                name: &self.app_name,
+            })?;
             if !output.status.success() {
                 return Err(Error::AppleScriptFailed(output.status.code().unwrap_or(1)));
             }
@@ -160,40 +263,187 @@ impl AutoLaunch {
 
     /// Check whether the AutoLaunch setting is enabled
     pub fn is_enabled(&self) -> Result<bool> {
-        if self.use_launch_agent {
-            Ok(self.get_file().exists())
+        Ok(self.status()?.is_enabled())
+    }
+
+    /// Get the [`Status`] of the AutoLaunch setting
+    ///
+    /// #### Launch Agent
+    ///
+    /// The existing plist is parsed, either XML or binary.
+    /// It is [`Status::DisabledByUser`] with `Disabled=true`, and [`Status::Stale`] if the
+    /// `ProgramArguments` differ from the `app_path` and `args`, or the plist cannot be parsed.
+    ///
+    /// ## Errors
+    ///
+    /// - home dir is not found
+    /// - failed to read the plist
+    /// - failed to execute the `osascript` command
+    pub fn status(&self) -> Result<Status> {
+        self.backend().status(self)
+    }
+
+    /// Get the custom [`Backend`], or the built-in one of `use_launch_agent`
+    pub(crate) fn backend(&self) -> &dyn Backend {
+        match &self.backend {
+            Some(backend) => backend.0.as_ref(),
+            None if self.use_launch_agent => &LaunchAgent,
+            None => &AppleScript,
+        }
+    }
+
+    fn launch_agent_status(&self) -> Result<Status> {
+        let data = match fs::read(self.get_file()?) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Status::Disabled),
+            Err(e) => return Err(e.into()),
+        };
+        // a plist which cannot be parsed is not loaded by launchd either
+        let plist = plist::parse(&data).ok();
+        let agent = plist.as_ref().and_then(Value::as_dictionary);
+        if agent
+            .and_then(|agent| agent.get("Disabled"))
+            .and_then(Value::as_bool)
+            == Some(true)
+        {
+            return Ok(Status::DisabledByUser);
+        }
+
+        let expected = self.get_program_args();
+        let found = agent.map(get_program_args).unwrap_or_default();
+        if found == expected {
+            Ok(Status::Enabled)
         } else {
-            let command = "get the name of every login item";
-            let output = exec_apple_script(command)?;
-            let mut enable = false;
-            if output.status.success() {
-                let stdout = std::str::from_utf8(&output.stdout).unwrap_or("");
-                let mut stdout = stdout.split(",").map(|x| x.trim());
-                enable = stdout.find(|x| x == &self.app_name).is_some();
+            Ok(Status::Stale {
+                expected: format_command(&expected),
+                found: format_command(&found),
+            })
+        }
+    }
+
+    /// Get the `ProgramArguments` of the Launch Agent
+    fn get_program_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(delay) = self.delay_secs() {
+            // launchd has no delay, the shell sleeps and then replaces itself with the app
+            args.push("/bin/sh".to_string());
+            args.push("-c".to_string());
+            args.push(format!("sleep {}; exec \"$0\" \"$@\"", delay));
+        }
+        args.push(self.app_path.clone());
+        args.extend_from_slice(&self.args);
+        args
+    }
+
+    /// Check whether the login item of the `app_name` exists
+    fn is_login_item(&self) -> Result<bool> {
+        if let Some(root) = &self.sandbox_root {
+            Ok(sandbox::value_names(root, sandbox::LOGIN_ITEMS_KEY).contains(&self.app_name))
+        } else {
+            let output = exec_apple_script(LoginItemCommand::GetNames)?;
+            if !output.status.success() {
+                return Ok(false);
             }
-            Ok(enable)
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            Ok(parse_string_list(&stdout)?.contains(&self.app_name))
         }
     }
 
     /// get the plist file path
-    fn get_file(&self) -> PathBuf {
-        get_dir().join(format!("{}.plist", self.app_name))
+    fn get_file(&self) -> Result<PathBuf> {
+        Ok(self.get_dir()?.join(format!("{}.plist", self.app_name)))
+    }
+
+    /// Get the Launch Agent Dir
+    fn get_dir(&self) -> Result<PathBuf> {
+        let home = match &self.home_dir {
+            Some(home) => home.clone(),
+            None => dirs::home_dir().ok_or(Error::HomeDirNotFound)?,
+        };
+        Ok(home.join("Library").join("LaunchAgents"))
     }
 }
 
-/// Get the Launch Agent Dir
-fn get_dir() -> PathBuf {
-    dirs::home_dir()
-        .unwrap()
-        .join("Library")
-        .join("LaunchAgents")
+impl Backend for LaunchAgent {
+    fn enable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.enable_launch_agent()
+    }
+
+    fn disable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.disable_launch_agent()
+    }
+
+    fn status(&self, auto: &AutoLaunch) -> Result<Status> {
+        auto.launch_agent_status()
+    }
+
+    fn describe(&self, auto: &AutoLaunch) -> String {
+        let file = match auto.get_file() {
+            Ok(file) => file.display().to_string(),
+            Err(_) => format!("~/Library/LaunchAgents/{}.plist", auto.app_name),
+        };
+        format!("Launch Agent {}", file)
+    }
+}
+
+impl Backend for AppleScript {
+    fn enable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.enable_login_item()
+    }
+
+    fn disable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.disable_login_item()
+    }
+
+    fn status(&self, auto: &AutoLaunch) -> Result<Status> {
+        if auto.is_login_item()? {
+            Ok(Status::Enabled)
+        } else {
+            Ok(Status::Disabled)
+        }
+    }
+
+    fn describe(&self, auto: &AutoLaunch) -> String {
+        format!("login item {}", auto.app_name)
+    }
+}
+
+/// Get the program and its args of a parsed Launch Agent, `Program` if there are no
+/// `ProgramArguments`
+fn get_program_args(agent: &Dictionary) -> Vec<String> {
+    match agent.get("ProgramArguments").and_then(Value::as_array) {
+        Some(args) => args
+            .iter()
+            .map(|arg| arg.as_str().unwrap_or_default().to_string())
+            .collect(),
+        None => agent
+            .get("Program")
+            .and_then(Value::as_str)
+            .map(|program| vec![program.to_string()])
+            .unwrap_or_default(),
+    }
+}
+
+/// Join the program and its args into a command line, quoted as the shell does
+fn format_command(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && !arg.contains(|c: char| c.is_whitespace() || "\"'\\$`;&|<>()*?#~".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-/// Execute the specific AppleScript
-fn exec_apple_script(cmd_suffix: &str) -> Result<Output> {
-    let command = format!("tell application \"System Events\" to {}", cmd_suffix);
+/// Execute the command on the login items, the result is printed as source
+fn exec_apple_script(command: LoginItemCommand) -> Result<Output> {
     let output = Command::new("osascript")
-        .args(vec!["-e", &command])
+        .args(["-s", "s", "-e", &command.to_script()])
         .output()?;
     Ok(output)
 }
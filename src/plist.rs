@@ -0,0 +1,786 @@
+//! Reading and writing [property lists], such as the plist of a Launch Agent.
+//!
+//! Both the XML and the binary (`bplist00`) formats are read, since tools like `defaults`
+//! rewrite the plists as binary. Only the XML format is written.
+//!
+//! [property lists]: https://developer.apple.com/library/archive/documentation/General/Conceptual/DevPedia-CocoaCore/PropertyList.html
+
+use crate::{Error, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The header of an XML property list
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+    <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+    \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+    <plist version=\"1.0\">\n";
+
+/// The magic number of a binary property list
+const BINARY_MAGIC: &[u8] = b"bplist00";
+
+/// Seconds from the Unix epoch to 2001-01-01, the epoch of the dates of a binary plist
+const APPLE_EPOCH: i64 = 978_307_200;
+
+/// How deep the arrays and dictionaries may be nested
+const MAX_DEPTH: usize = 256;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A value of a property list
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Date(SystemTime),
+    Data(Vec<u8>),
+    Array(Vec<Value>),
+    Dictionary(Dictionary),
+}
+
+/// A dictionary of a property list, the keys are kept in order of insertion
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dictionary {
+    entries: Vec<(String, Value)>,
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary {
+        Dictionary::default()
+    }
+
+    /// Set the value of the key, in place if it already exists
+    pub fn insert(&mut self, key: &str, value: impl Into<Value>) {
+        let value = value.into();
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    /// Get the value of the key
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Remove the key, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Iterate over the keys and values in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Value {
+    /// Get the string, if it is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get the boolean, if it is one
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Get the integer, if it is one
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Get the items, if it is an array
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Get the dictionary, if it is one
+    pub fn as_dictionary(&self) -> Option<&Dictionary> {
+        match self {
+            Value::Dictionary(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Serialize the value as the root of an XML property list, indented with tabs
+    /// as `plutil` does
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(XML_HEADER);
+        self.write_xml(&mut xml, 0);
+        xml.push_str("</plist>\n");
+        xml
+    }
+
+    fn write_xml(&self, xml: &mut String, depth: usize) {
+        let indent = "\t".repeat(depth);
+        match self {
+            Value::String(s) => {
+                xml.push_str(&format!("{}<string>{}</string>\n", indent, escape_xml(s)))
+            }
+            Value::Integer(i) => xml.push_str(&format!("{}<integer>{}</integer>\n", indent, i)),
+            Value::Real(r) => xml.push_str(&format!("{}<real>{}</real>\n", indent, r)),
+            Value::Boolean(b) => xml.push_str(&format!("{}<{}/>\n", indent, b)),
+            Value::Date(date) => {
+                xml.push_str(&format!("{}<date>{}</date>\n", indent, format_date(*date)))
+            }
+            Value::Data(data) => {
+                xml.push_str(&format!("{}<data>{}</data>\n", indent, encode_base64(data)))
+            }
+            Value::Array(items) if items.is_empty() => {
+                xml.push_str(&format!("{}<array/>\n", indent))
+            }
+            Value::Array(items) => {
+                xml.push_str(&format!("{}<array>\n", indent));
+                for item in items {
+                    item.write_xml(xml, depth + 1);
+                }
+                xml.push_str(&format!("{}</array>\n", indent));
+            }
+            Value::Dictionary(dict) if dict.is_empty() => {
+                xml.push_str(&format!("{}<dict/>\n", indent))
+            }
+            Value::Dictionary(dict) => {
+                xml.push_str(&format!("{}<dict>\n", indent));
+                for (key, value) in dict.iter() {
+                    xml.push_str(&format!("{}\t<key>{}</key>\n", indent, escape_xml(key)));
+                    value.write_xml(xml, depth + 1);
+                }
+                xml.push_str(&format!("{}</dict>\n", indent));
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Value {
+        Value::Integer(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(r: f64) -> Value {
+        Value::Real(r)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Boolean(b)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Value {
+        Value::Array(items)
+    }
+}
+
+impl From<Dictionary> for Value {
+    fn from(dict: Dictionary) -> Value {
+        Value::Dictionary(dict)
+    }
+}
+
+/// Escape the text of an XML element
+///
+/// The characters which XML 1.0 cannot hold, such as most of the control characters,
+/// are replaced by U+FFFD so that the document stays valid.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parse a property list, either XML or binary
+///
+/// ## Errors
+///
+/// - the data is neither a valid XML nor a valid binary property list
+pub fn parse(data: &[u8]) -> Result<Value> {
+    if data.starts_with(BINARY_MAGIC) {
+        return BinaryReader::new(data)?.read_top();
+    }
+    let text = std::str::from_utf8(data).map_err(|_| invalid("the XML is not UTF-8"))?;
+    XmlReader { text, pos: 0 }.read_document()
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidPlist(message.to_string())
+}
+
+/// A tag of the XML format, the attributes are ignored
+#[derive(Debug, PartialEq, Eq)]
+enum Tag<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Empty(&'a str),
+}
+
+/// Reads the elements of the XML format
+struct XmlReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn read_document(&mut self) -> Result<Value> {
+        let value = match self.next_tag()? {
+            Tag::Open("plist") => {
+                let tag = self.next_tag()?;
+                let value = self.read_value(tag, 0)?;
+                if self.next_tag()? != Tag::Close("plist") {
+                    return Err(invalid("expected </plist>"));
+                }
+                value
+            }
+            // the root element is optional
+            tag => self.read_value(tag, 0)?,
+        };
+        self.skip_misc()?;
+        if self.pos < self.text.len() {
+            return Err(invalid("unexpected content after the root element"));
+        }
+        Ok(value)
+    }
+
+    fn read_value(&mut self, tag: Tag, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deeply"));
+        }
+        let value = match tag {
+            Tag::Open("string") => Value::String(self.read_text("string")?),
+            Tag::Empty("string") => Value::String(String::new()),
+            Tag::Open("integer") => {
+                let text = self.read_text("integer")?;
+                let text = text.trim();
+                let integer = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                    None => text.parse().ok(),
+                };
+                Value::Integer(integer.ok_or_else(|| invalid("invalid <integer>"))?)
+            }
+            Tag::Open("real") => Value::Real(
+                self.read_text("real")?
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("invalid <real>"))?,
+            ),
+            Tag::Empty("true") => Value::Boolean(true),
+            Tag::Empty("false") => Value::Boolean(false),
+            Tag::Open(name @ ("true" | "false")) => {
+                if !self.read_text(name)?.trim().is_empty() {
+                    return Err(invalid("unexpected text in a boolean"));
+                }
+                Value::Boolean(name == "true")
+            }
+            Tag::Open("date") => Value::Date(parse_date(self.read_text("date")?.trim())?),
+            Tag::Open("data") => Value::Data(decode_base64(&self.read_text("data")?)?),
+            Tag::Empty("data") => Value::Data(Vec::new()),
+            Tag::Open("array") => {
+                let mut items = Vec::new();
+                loop {
+                    match self.next_tag()? {
+                        Tag::Close("array") => break,
+                        tag => items.push(self.read_value(tag, depth + 1)?),
+                    }
+                }
+                Value::Array(items)
+            }
+            Tag::Empty("array") => Value::Array(Vec::new()),
+            Tag::Open("dict") => {
+                let mut dict = Dictionary::new();
+                loop {
+                    let key = match self.next_tag()? {
+                        Tag::Close("dict") => break,
+                        Tag::Open("key") => self.read_text("key")?,
+                        Tag::Empty("key") => String::new(),
+                        _ => return Err(invalid("expected <key> in <dict>")),
+                    };
+                    let tag = self.next_tag()?;
+                    dict.insert(&key, self.read_value(tag, depth + 1)?);
+                }
+                Value::Dictionary(dict)
+            }
+            Tag::Empty("dict") => Value::Dictionary(Dictionary::new()),
+            Tag::Close(name) => return Err(Error::InvalidPlist(format!("unexpected </{}>", name))),
+            Tag::Open(name) | Tag::Empty(name) => {
+                return Err(Error::InvalidPlist(format!("unknown element <{}>", name)))
+            }
+        };
+        Ok(value)
+    }
+
+    /// Skip the whitespace, comments, processing instructions and the doctype
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            let rest = &self.text[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let end = if trimmed.starts_with("<?") {
+                "?>"
+            } else if trimmed.starts_with("<!--") {
+                "-->"
+            } else if trimmed.starts_with("<!DOCTYPE") {
+                ">"
+            } else {
+                return Ok(());
+            };
+            match trimmed.find(end) {
+                Some(i) => self.pos += i + end.len(),
+                None => return Err(invalid("unterminated markup")),
+            }
+        }
+    }
+
+    fn next_tag(&mut self) -> Result<Tag<'a>> {
+        self.skip_misc()?;
+        let rest = &self.text[self.pos..];
+        if !rest.starts_with('<') {
+            return Err(invalid("expected an element"));
+        }
+        let end = rest.find('>').ok_or_else(|| invalid("unterminated tag"))?;
+        self.pos += end + 1;
+        let inner = &rest[1..end];
+        let tag = if let Some(inner) = inner.strip_prefix('/') {
+            Tag::Close(inner.trim())
+        } else if let Some(inner) = inner.strip_suffix('/') {
+            Tag::Empty(tag_name(inner))
+        } else {
+            Tag::Open(tag_name(inner))
+        };
+        Ok(tag)
+    }
+
+    /// Read the text of the element up to its closing tag, decoding the references
+    fn read_text(&mut self, name: &str) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let i = rest
+                .find('<')
+                .ok_or_else(|| invalid("unterminated element"))?;
+            text.push_str(&decode_entities(&rest[..i])?);
+            let rest = &rest[i..];
+            self.pos += i;
+            if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = cdata
+                    .find("]]>")
+                    .ok_or_else(|| invalid("unterminated CDATA"))?;
+                text.push_str(&cdata[..end]);
+                self.pos += "<![CDATA[".len() + end + "]]>".len();
+            } else if rest.starts_with("<!--") {
+                self.skip_misc()?;
+            } else {
+                return match self.next_tag()? {
+                    Tag::Close(close) if close == name => Ok(text),
+                    _ => Err(Error::InvalidPlist(format!("expected </{}>", name))),
+                };
+            }
+        }
+    }
+}
+
+/// Get the name of a tag, without its attributes
+fn tag_name(inner: &str) -> &str {
+    inner.split_whitespace().next().unwrap_or("")
+}
+
+/// Decode the entity and character references of the XML text
+fn decode_entities(text: &str) -> Result<String> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        let end = rest[i..]
+            .find(';')
+            .ok_or_else(|| invalid("unterminated reference"))?;
+        let c = match &rest[i + 1..i + end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            name => match name.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        decoded.push(c.ok_or_else(|| invalid("unknown reference"))?);
+        rest = &rest[i + end + 1..];
+    }
+    decoded.push_str(rest);
+    Ok(decoded)
+}
+
+/// Reads the objects of the binary format
+///
+/// The trailer at the end gives the offset table, which gives the position of every object.
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    offset_size: usize,
+    ref_size: usize,
+    num_objects: u64,
+    top_object: u64,
+    table_offset: usize,
+    /// The objects being read, to detect cycles
+    stack: Vec<u64>,
+    /// The objects read so far, to bound the arrays and dictionaries which are referenced
+    /// over and over, since they are read again for every reference
+    read_count: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn new(data: &'a [u8]) -> Result<BinaryReader<'a>> {
+        if data.len() < BINARY_MAGIC.len() + 32 {
+            return Err(invalid("truncated binary plist"));
+        }
+        let trailer = &data[data.len() - 32..];
+        let reader = BinaryReader {
+            data,
+            offset_size: usize::from(trailer[6]),
+            ref_size: usize::from(trailer[7]),
+            num_objects: be_uint(&trailer[8..16]),
+            top_object: be_uint(&trailer[16..24]),
+            table_offset: usize::try_from(be_uint(&trailer[24..32]))
+                .map_err(|_| invalid("invalid offset table"))?,
+            stack: Vec::new(),
+            read_count: 0,
+        };
+        let table_len = reader
+            .num_objects
+            .checked_mul(reader.offset_size as u64)
+            .and_then(|len| len.checked_add(reader.table_offset as u64));
+        if !(1..=8).contains(&reader.offset_size)
+            || !(1..=8).contains(&reader.ref_size)
+            || reader.top_object >= reader.num_objects
+            || table_len.is_none_or(|len| len > (data.len() - 32) as u64)
+        {
+            return Err(invalid("invalid trailer"));
+        }
+        Ok(reader)
+    }
+
+    fn read_top(&mut self) -> Result<Value> {
+        self.read_object(self.top_object)
+    }
+
+    fn read_object(&mut self, index: u64) -> Result<Value> {
+        if index >= self.num_objects {
+            return Err(invalid("object reference out of range"));
+        }
+        if self.stack.contains(&index) || self.stack.len() > MAX_DEPTH {
+            return Err(invalid("cyclic or too deep objects"));
+        }
+        // every reference takes a byte at least, so unless the same arrays or dictionaries
+        // are referenced again, there are fewer objects than bytes
+        self.read_count += 1;
+        if self.read_count > self.data.len() {
+            return Err(invalid("too many objects"));
+        }
+        self.stack.push(index);
+        let pos = self.table_offset + index as usize * self.offset_size;
+        let offset = be_uint(self.slice(pos, self.offset_size)?) as usize;
+        let value = self.read_object_at(offset);
+        self.stack.pop();
+        value
+    }
+
+    fn read_object_at(&mut self, pos: usize) -> Result<Value> {
+        let marker = *self.slice(pos, 1)?.first().unwrap_or(&0);
+        let info = marker & 0x0f;
+        let value = match marker >> 4 {
+            0x0 if info == 0x8 => Value::Boolean(false),
+            0x0 if info == 0x9 => Value::Boolean(true),
+            0x1 => Value::Integer(self.read_int(pos)?.0),
+            0x2 => {
+                let bytes = self.slice(pos + 1, 1 << info)?;
+                match bytes.len() {
+                    4 => Value::Real(f64::from(f32::from_bits(be_uint(bytes) as u32))),
+                    8 => Value::Real(f64::from_bits(be_uint(bytes))),
+                    _ => return Err(invalid("invalid real size")),
+                }
+            }
+            0x3 if info == 0x3 => {
+                let seconds = f64::from_bits(be_uint(self.slice(pos + 1, 8)?));
+                Value::Date(apple_time(seconds)?)
+            }
+            0x4 => {
+                let (len, start) = self.read_length(pos, info)?;
+                Value::Data(self.slice(start, len)?.to_vec())
+            }
+            0x5 => {
+                let (len, start) = self.read_length(pos, info)?;
+                let bytes = self.slice(start, len)?;
+                if !bytes.is_ascii() {
+                    return Err(invalid("invalid ASCII string"));
+                }
+                Value::String(String::from_utf8_lossy(bytes).into_owned())
+            }
+            0x6 => {
+                let (len, start) = self.read_length(pos, info)?;
+                let len = len
+                    .checked_mul(2)
+                    .ok_or_else(|| invalid("string too long"))?;
+                let units = self
+                    .slice(start, len)?
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect::<Vec<_>>();
+                Value::String(String::from_utf16(&units).map_err(|_| invalid("invalid UTF-16"))?)
+            }
+            // a UID of a keyed archive
+            0x8 => Value::Integer(be_uint(self.slice(pos + 1, usize::from(info) + 1)?) as i64),
+            // arrays and sets
+            0xa | 0xc => {
+                let (len, start) = self.read_length(pos, info)?;
+                let refs = self.read_refs(start, len)?;
+                let mut items = Vec::with_capacity(refs.len());
+                for index in refs {
+                    items.push(self.read_object(index)?);
+                }
+                Value::Array(items)
+            }
+            0xd => {
+                let (len, start) = self.read_length(pos, info)?;
+                let refs = self.read_refs(start, len.saturating_mul(2))?;
+                let (keys, values) = refs.split_at(len);
+                let mut dict = Dictionary::new();
+                for (&key, &value) in keys.iter().zip(values) {
+                    let key = match self.read_object(key)? {
+                        Value::String(key) => key,
+                        _ => return Err(invalid("the key is not a string")),
+                    };
+                    let value = self.read_object(value)?;
+                    dict.insert(&key, value);
+                }
+                Value::Dictionary(dict)
+            }
+            _ => {
+                return Err(Error::InvalidPlist(format!(
+                    "unknown object 0x{:02x}",
+                    marker
+                )))
+            }
+        };
+        Ok(value)
+    }
+
+    /// Read the integer object at the position, with the position after it
+    fn read_int(&self, pos: usize) -> Result<(i64, usize)> {
+        let marker = *self.slice(pos, 1)?.first().unwrap_or(&0);
+        if marker >> 4 != 0x1 || marker & 0x0f > 4 {
+            return Err(invalid("invalid integer"));
+        }
+        let size = 1 << (marker & 0x0f);
+        let bytes = self.slice(pos + 1, size)?;
+        // 16 bytes hold the unsigned values above `i64::MAX`, which are truncated
+        let value = be_uint(&bytes[size.saturating_sub(8)..]) as i64;
+        Ok((value, pos + 1 + size))
+    }
+
+    /// Read the length of the object at the position, which follows as an integer
+    /// if it does not fit into the marker, with the position of the content
+    fn read_length(&self, pos: usize, info: u8) -> Result<(usize, usize)> {
+        if info != 0x0f {
+            return Ok((usize::from(info), pos + 1));
+        }
+        let (len, start) = self.read_int(pos + 1)?;
+        let len = usize::try_from(len).map_err(|_| invalid("invalid length"))?;
+        Ok((len, start))
+    }
+
+    fn read_refs(&self, start: usize, count: usize) -> Result<Vec<u64>> {
+        let len = count
+            .checked_mul(self.ref_size)
+            .ok_or_else(|| invalid("too many references"))?;
+        Ok(self
+            .slice(start, len)?
+            .chunks_exact(self.ref_size)
+            .map(be_uint)
+            .collect())
+    }
+
+    fn slice(&self, start: usize, len: usize) -> Result<&'a [u8]> {
+        start
+            .checked_add(len)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| invalid("truncated binary plist"))
+    }
+}
+
+/// Read a big endian unsigned integer of up to 8 bytes
+fn be_uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | u64::from(byte))
+}
+
+/// Convert the seconds since 2001-01-01 of a binary plist
+fn apple_time(seconds: f64) -> Result<SystemTime> {
+    let seconds = seconds + APPLE_EPOCH as f64;
+    let duration =
+        Duration::try_from_secs_f64(seconds.abs()).map_err(|_| invalid("invalid date"))?;
+    let time = if seconds >= 0.0 {
+        UNIX_EPOCH.checked_add(duration)
+    } else {
+        UNIX_EPOCH.checked_sub(duration)
+    };
+    time.ok_or_else(|| invalid("invalid date"))
+}
+
+/// Parse a date of the XML format, `YYYY-MM-DDTHH:MM:SSZ`
+fn parse_date(text: &str) -> Result<SystemTime> {
+    let number = |range: std::ops::Range<usize>| {
+        text.get(range)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .ok_or_else(|| invalid("invalid date"))
+    };
+    let bytes = text.as_bytes();
+    if bytes.len() != 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || bytes[10] != b'T'
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || bytes[19] != b'Z'
+    {
+        return Err(invalid("invalid date"));
+    }
+    let days = days_from_civil(number(0..4)?, number(5..7)?, number(8..10)?);
+    let seconds = days * 86400 + number(11..13)? * 3600 + number(14..16)? * 60 + number(17..19)?;
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    };
+    time.ok_or_else(|| invalid("invalid date"))
+}
+
+/// Format a date as the XML format does, truncated to seconds
+fn format_date(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    };
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Count the days from 1970-01-01 to the date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Get the date of the proleptic Gregorian calendar from the days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode the base64 of a `<data>` element, the whitespace is ignored
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for b in text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace() && *b != b'=')
+    {
+        let value = BASE64
+            .iter()
+            .position(|&c| c == b)
+            .ok_or_else(|| invalid("invalid base64"))?;
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Ok(data)
+}
@@ -0,0 +1,68 @@
+//! In-memory stand-ins for the system settings which are not files,
+//! used by the backends in place of the real ones when a sandbox root is set
+//! with [`AutoLaunchBuilder::set_sandbox_root`](crate::AutoLaunchBuilder::set_sandbox_root).
+//!
+//! Every sandbox root has its own store of values, grouped by keys:
+//!
+//! - the Windows registry values under `HKLM\{path}` and `HKCU\{path}`,
+//!   e.g. `HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run`, strings are stored as UTF-8
+//! - the macOS login items under [`LOGIN_ITEMS_KEY`], named by the app name with the app path as value
+//!
+//! The store lives as long as the process, use [`clear`] to reset a sandbox root.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+/// The key of the macOS login items
+pub const LOGIN_ITEMS_KEY: &str = "LoginItems";
+
+type Values = BTreeMap<String, Vec<u8>>;
+
+/// The values of every sandbox root, by key and name
+static STORE: Mutex<BTreeMap<PathBuf, BTreeMap<String, Values>>> = Mutex::new(BTreeMap::new());
+
+fn lock() -> MutexGuard<'static, BTreeMap<PathBuf, BTreeMap<String, Values>>> {
+    // the store is left consistent even if a holder panicked
+    STORE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Get a value of the sandbox root
+pub fn get_value(root: &Path, key: &str, name: &str) -> Option<Vec<u8>> {
+    lock().get(root)?.get(key)?.get(name).cloned()
+}
+
+/// Set a value of the sandbox root, e.g. to simulate the changes made by the user
+pub fn set_value(root: &Path, key: &str, name: &str, data: &[u8]) {
+    lock()
+        .entry(root.to_path_buf())
+        .or_default()
+        .entry(key.to_string())
+        .or_default()
+        .insert(name.to_string(), data.to_vec());
+}
+
+/// Remove a value of the sandbox root, returns whether it existed
+pub fn remove_value(root: &Path, key: &str, name: &str) -> bool {
+    lock()
+        .get_mut(root)
+        .and_then(|keys| keys.get_mut(key))
+        .and_then(|values| values.remove(name))
+        .is_some()
+}
+
+/// Get the names of the values under a key of the sandbox root
+pub fn value_names(root: &Path, key: &str) -> Vec<String> {
+    lock()
+        .get(root)
+        .and_then(|keys| keys.get(key))
+        .map(|values| values.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Remove every value of the sandbox root
+pub fn clear(root: &Path) {
+    lock().remove(root);
+}
@@ -1,5 +1,8 @@
-use crate::{AutoLaunch, Result, WindowsEnableMode};
-use std::io;
+use crate::{
+    backend::{Backend, Registry},
+    sandbox, AutoLaunch, Result, Scope, Status, WindowsEnableMode,
+};
+use std::{collections::BTreeMap, io};
 use windows_registry::{Key, CURRENT_USER, LOCAL_MACHINE};
 use windows_result::HRESULT;
 
@@ -12,6 +15,98 @@ const TASK_MANAGER_OVERRIDE_ENABLED_VALUE: [u8; 12] = [
 const E_ACCESSDENIED: HRESULT = HRESULT::from_win32(0x80070005_u32);
 const E_FILENOTFOUND: HRESULT = HRESULT::from_win32(0x80070002_u32);
 
+/// Characters which must be escaped with `^` in an unquoted word of `cmd.exe`
+const CMD_SPECIAL: &[char] = &['^', '&', '|', '<', '>', '(', ')', '"'];
+
+/// Escape the special characters of `cmd.exe` in an unquoted word with `^`
+///
+/// `%` cannot be escaped that way, instead a `^` is put after it so the variable name
+/// is never found, and the `^` is removed afterwards.
+/// A `%` which is trailing or followed by an escaped character is left alone.
+fn escape_cmd(word: &str) -> String {
+    let mut escaped = String::with_capacity(word.len());
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if CMD_SPECIAL.contains(&c) => {
+                escaped.push('^');
+                escaped.push(c);
+            }
+            '%' if chars.peek().is_some_and(|c| !CMD_SPECIAL.contains(c)) => escaped.push_str("%^"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quote an arg of the app as `CommandLineToArgvW` reads it, if it is empty
+/// or contains a space, a tab or a quote
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // the backslashes before a quote are doubled, and the quote is escaped
+        let count = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.push_str(&"\\".repeat(count));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // so are the backslashes before the closing quote
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Quote a path for `cmd.exe`, a path cannot contain `"`
+///
+/// Within the quotes only `%` is special, so it is followed by `""`, which leaves
+/// the quotes and enters them again, so that the variable name is never found.
+fn quote_cmd_path(path: &str) -> String {
+    format!("\"{}\"", path.replace('%', "%\"\""))
+}
+
+/// A root key of the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hive {
+    LocalMachine,
+    CurrentUser,
+}
+
+impl Hive {
+    fn scope(self) -> Scope {
+        match self {
+            Hive::LocalMachine => Scope::System,
+            Hive::CurrentUser => Scope::User,
+        }
+    }
+
+    fn key(self) -> &'static Key {
+        match self {
+            Hive::LocalMachine => LOCAL_MACHINE,
+            Hive::CurrentUser => CURRENT_USER,
+        }
+    }
+
+    /// The path of a subkey in the sandbox store
+    fn sandbox_key(self, path: &str) -> String {
+        match self {
+            Hive::LocalMachine => format!(r"HKLM\{}", path),
+            Hive::CurrentUser => format!(r"HKCU\{}", path),
+        }
+    }
+}
+
 /// Windows implement
 impl AutoLaunch {
     /// Create a new AutoLaunch instance
@@ -34,16 +129,27 @@ impl AutoLaunch {
             app_path: app_path.into(),
             enable_mode,
             args: args.iter().map(|s| s.as_ref().to_string()).collect(),
+            working_directory: None,
+            envs: BTreeMap::new(),
+            delay: None,
+            sandbox_root: None,
+            backend: None,
         }
     }
 
     /// Enable the AutoLaunch setting
     ///
+    /// A custom [`Backend`] set with the builder is used in place of the registry.
+    ///
     /// ## Errors
     ///
     /// - failed to open the registry key
     /// - failed to set value
     pub fn enable(&self) -> Result<()> {
+        self.backend().enable(self)
+    }
+
+    fn enable_registry(&self) -> Result<()> {
         match self.enable_mode {
             WindowsEnableMode::Dynamic => self
                 .enable_as_admin()
@@ -64,46 +170,40 @@ impl AutoLaunch {
     }
 
     fn enable_as_admin(&self) -> windows_registry::Result<()> {
-        self.enable_with_root_key(LOCAL_MACHINE)
+        self.enable_with_root_key(Hive::LocalMachine)
     }
 
     fn enable_as_current_user(&self) -> windows_registry::Result<()> {
-        self.enable_with_root_key(CURRENT_USER)
+        self.enable_with_root_key(Hive::CurrentUser)
     }
 
-    fn enable_with_root_key(&self, root_key: &Key) -> windows_registry::Result<()> {
-        root_key.create(AL_REGKEY)?.set_string(
-            &self.app_name,
-            format!("{} {}", &self.app_path, &self.args.join(" ")),
-        )?;
+    fn enable_with_root_key(&self, hive: Hive) -> windows_registry::Result<()> {
+        self.set_string(hive, AL_REGKEY, &self.get_command())?;
 
-        match root_key
-            .options()
-            .write()
-            .open(TASK_MANAGER_OVERRIDE_REGKEY)
-        {
-            Ok(key) => key.set_bytes(
-                &self.app_name,
-                windows_registry::Type::Bytes,
-                &TASK_MANAGER_OVERRIDE_ENABLED_VALUE,
-            )?,
-            Err(error) if error.code() == E_FILENOTFOUND => {
-                return Ok(());
-            }
-            Err(error) => {
-                return Err(error);
-            }
+        match self.set_bytes_if_key_exists(
+            hive,
+            TASK_MANAGER_OVERRIDE_REGKEY,
+            &TASK_MANAGER_OVERRIDE_ENABLED_VALUE,
+        ) {
+            Ok(_) => Ok(()),
+            Err(error) if error.code() == E_FILENOTFOUND => Ok(()),
+            Err(error) => Err(error),
         }
-        Ok(())
     }
 
     /// Disable the AutoLaunch setting
     ///
+    /// A custom [`Backend`] set with the builder is used in place of the registry.
+    ///
     /// ## Errors
     ///
     /// - failed to open the registry key
     /// - failed to delete value
     pub fn disable(&self) -> Result<()> {
+        self.backend().disable(self)
+    }
+
+    fn disable_registry(&self) -> Result<()> {
         // try to delete both admin and current user registry values
         if let Err(error) = self.disable_as_admin() {
             if error.code() == E_ACCESSDENIED {
@@ -123,20 +223,15 @@ impl AutoLaunch {
     }
 
     fn disable_as_admin(&self) -> windows_registry::Result<()> {
-        self.disable_with_root_key(LOCAL_MACHINE)
+        self.disable_with_root_key(Hive::LocalMachine)
     }
 
     fn disable_as_current_user(&self) -> windows_registry::Result<()> {
-        self.disable_with_root_key(CURRENT_USER)
+        self.disable_with_root_key(Hive::CurrentUser)
     }
 
-    fn disable_with_root_key(&self, root_key: &Key) -> windows_registry::Result<()> {
-        match root_key
-            .options()
-            .write()
-            .open(AL_REGKEY)
-            .and_then(|key| key.remove_value(&self.app_name))
-        {
+    fn disable_with_root_key(&self, hive: Hive) -> windows_registry::Result<()> {
+        match self.remove_value(hive, AL_REGKEY) {
             Ok(_) => Ok(()),
             Err(error) if error.code() == E_FILENOTFOUND => Ok(()),
             Err(error) => Err(error),
@@ -144,36 +239,84 @@ impl AutoLaunch {
     }
 
     /// Check whether the AutoLaunch setting is enabled
+    ///
+    /// See [`AutoLaunch::status`] for the details.
     pub fn is_enabled(&self) -> Result<bool> {
-        let is_registered =
-            self.is_registered(LOCAL_MACHINE)? || self.is_registered(CURRENT_USER)?;
-        if !is_registered {
-            return Ok(false);
-        }
-        let is_task_manager_enabled = self.is_task_manager_enabled(LOCAL_MACHINE)?
-            && self.is_task_manager_enabled(CURRENT_USER)?;
-        Ok(is_task_manager_enabled)
-    }
-
-    fn is_registered(&self, root_key: &Key) -> io::Result<bool> {
-        let registered = match root_key
-            .open(AL_REGKEY)
-            .and_then(|key| key.get_string(&self.app_name))
-        {
-            Ok(_) => true,
-            Err(error) if error.code() == E_FILENOTFOUND => false,
-            Err(error) => {
-                return Err(error.into());
+        Ok(self.status()?.is_enabled())
+    }
+
+    /// Get the [`Status`] of the AutoLaunch setting
+    ///
+    /// The hives of the `enable_mode` are looked up first, and the other one then,
+    /// which gives [`Status::RegisteredElsewhere`].
+    /// It is [`Status::DisabledByUser`] if the startup is disabled inside the Task Manager
+    /// or the Windows settings UI, and [`Status::Stale`] if the command line differs.
+    ///
+    /// ## Errors
+    ///
+    /// - failed to read the registry values
+    pub fn status(&self) -> Result<Status> {
+        self.backend().status(self)
+    }
+
+    /// Get the custom [`Backend`], or the registry
+    pub(crate) fn backend(&self) -> &dyn Backend {
+        match &self.backend {
+            Some(backend) => backend.0.as_ref(),
+            None => &Registry,
+        }
+    }
+
+    fn registry_status(&self) -> Result<Status> {
+        let own: &[Hive] = match self.enable_mode {
+            WindowsEnableMode::Dynamic => &[Hive::LocalMachine, Hive::CurrentUser],
+            WindowsEnableMode::CurrentUser => &[Hive::CurrentUser],
+            WindowsEnableMode::System => &[Hive::LocalMachine],
+        };
+        let others = [Hive::LocalMachine, Hive::CurrentUser]
+            .into_iter()
+            .filter(|hive| !own.contains(hive));
+
+        let mut registered = None;
+        for hive in own.iter().copied().chain(others) {
+            if let Some(command) = self.get_registered(hive)? {
+                registered = Some((hive, command));
+                break;
             }
+        }
+        let (hive, found) = match registered {
+            Some(registered) => registered,
+            None => return Ok(Status::Disabled),
         };
-        Ok(registered)
+
+        let is_task_manager_enabled = self.is_task_manager_enabled(Hive::LocalMachine)?
+            && self.is_task_manager_enabled(Hive::CurrentUser)?;
+        if !is_task_manager_enabled {
+            return Ok(Status::DisabledByUser);
+        }
+        if !own.contains(&hive) {
+            return Ok(Status::RegisteredElsewhere(hive.scope()));
+        }
+
+        let expected = self.get_command();
+        if found == expected {
+            Ok(Status::Enabled)
+        } else {
+            Ok(Status::Stale { expected, found })
+        }
     }
 
-    fn is_task_manager_enabled(&self, root_key: &Key) -> io::Result<bool> {
-        let task_manager_enabled = match root_key
-            .open(TASK_MANAGER_OVERRIDE_REGKEY)
-            .and_then(|key| key.get_value(&self.app_name))
-        {
+    /// Get the command line registered in the `Run` key of the hive
+    fn get_registered(&self, hive: Hive) -> io::Result<Option<String>> {
+        match self.get_string(hive, AL_REGKEY) {
+            Ok(command) => Ok(Some(command)),
+            Err(error) if error.code() == E_FILENOTFOUND => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn is_task_manager_enabled(&self, hive: Hive) -> io::Result<bool> {
+        let task_manager_enabled = match self.get_bytes(hive, TASK_MANAGER_OVERRIDE_REGKEY) {
             Ok(value) => last_eight_bytes_all_zeros(&value).unwrap_or(true),
             Err(error) if error.code() == E_FILENOTFOUND => true,
             Err(error) => {
@@ -182,6 +325,153 @@ impl AutoLaunch {
         };
         Ok(task_manager_enabled)
     }
+
+    /// Get the command line written into the `Run` key
+    ///
+    /// The `Run` key has no working directory, environment nor delay, so the app is started
+    /// through `cmd.exe` which waits for the `delay`, sets the `envs` and changes to
+    /// the `working_directory` first. Every word is then escaped, so that `cmd.exe`
+    /// passes the `args` to the app unchanged.
+    ///
+    /// The `args` are quoted the same way with or without `cmd.exe`, see [`quote_arg`].
+    fn get_command(&self) -> String {
+        let delay = self.delay_secs();
+        if self.working_directory.is_none() && self.envs.is_empty() && delay.is_none() {
+            let args = self.args.iter().map(|arg| quote_arg(arg));
+            return format!("{} {}", self.app_path, args.collect::<Vec<_>>().join(" "));
+        }
+
+        let mut command = String::from("cmd.exe /d /c ");
+        if let Some(delay) = delay {
+            // the app is started even if the wait fails
+            command.push_str(&format!("timeout /t {} /nobreak >nul & ", delay));
+        }
+        for (key, value) in &self.envs {
+            // a space before `&&` would end up in the value
+            command.push_str(&format!("set {}={}&& ", escape_cmd(key), escape_cmd(value)));
+        }
+        if let Some(dir) = &self.working_directory {
+            command.push_str(&format!(
+                "cd /d {} && ",
+                quote_cmd_path(&dir.display().to_string())
+            ));
+        }
+        command.push_str(&quote_cmd_path(&self.app_path));
+        for arg in &self.args {
+            command.push(' ');
+            command.push_str(&escape_cmd(&quote_arg(arg)));
+        }
+        command
+    }
+
+    /// Set the string value named `app_name` of the subkey, creating the subkey if needed
+    fn set_string(&self, hive: Hive, path: &str, value: &str) -> windows_registry::Result<()> {
+        match &self.sandbox_root {
+            Some(root) => {
+                sandbox::set_value(
+                    root,
+                    &hive.sandbox_key(path),
+                    &self.app_name,
+                    value.as_bytes(),
+                );
+                Ok(())
+            }
+            None => hive.key().create(path)?.set_string(&self.app_name, value),
+        }
+    }
+
+    /// Set the binary value named `app_name` of the subkey, which must exist
+    ///
+    /// The subkeys always exist in the sandbox.
+    fn set_bytes_if_key_exists(
+        &self,
+        hive: Hive,
+        path: &str,
+        value: &[u8],
+    ) -> windows_registry::Result<()> {
+        match &self.sandbox_root {
+            Some(root) => {
+                sandbox::set_value(root, &hive.sandbox_key(path), &self.app_name, value);
+                Ok(())
+            }
+            None => hive.key().options().write().open(path)?.set_bytes(
+                &self.app_name,
+                windows_registry::Type::Bytes,
+                value,
+            ),
+        }
+    }
+
+    /// Remove the value named `app_name` of the subkey
+    fn remove_value(&self, hive: Hive, path: &str) -> windows_registry::Result<()> {
+        match &self.sandbox_root {
+            Some(root) => {
+                if sandbox::remove_value(root, &hive.sandbox_key(path), &self.app_name) {
+                    Ok(())
+                } else {
+                    Err(E_FILENOTFOUND.into())
+                }
+            }
+            None => hive
+                .key()
+                .options()
+                .write()
+                .open(path)
+                .and_then(|key| key.remove_value(&self.app_name)),
+        }
+    }
+
+    /// Get the string value named `app_name` of the subkey
+    fn get_string(&self, hive: Hive, path: &str) -> windows_registry::Result<String> {
+        match &self.sandbox_root {
+            Some(root) => sandbox::get_value(root, &hive.sandbox_key(path), &self.app_name)
+                .map(|value| String::from_utf8_lossy(&value).into_owned())
+                .ok_or_else(|| E_FILENOTFOUND.into()),
+            None => hive
+                .key()
+                .open(path)
+                .and_then(|key| key.get_string(&self.app_name)),
+        }
+    }
+
+    /// Get the raw bytes of the value named `app_name` of the subkey
+    fn get_bytes(&self, hive: Hive, path: &str) -> windows_registry::Result<Vec<u8>> {
+        match &self.sandbox_root {
+            Some(root) => sandbox::get_value(root, &hive.sandbox_key(path), &self.app_name)
+                .ok_or_else(|| E_FILENOTFOUND.into()),
+            None => hive
+                .key()
+                .open(path)
+                .and_then(|key| key.get_value(&self.app_name))
+                .map(|value| value.to_vec()),
+        }
+    }
+}
+
+impl Backend for Registry {
+    fn enable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.enable_registry()
+    }
+
+    fn disable(&self, auto: &AutoLaunch) -> Result<()> {
+        auto.disable_registry()
+    }
+
+    fn status(&self, auto: &AutoLaunch) -> Result<Status> {
+        auto.registry_status()
+    }
+
+    fn describe(&self, auto: &AutoLaunch) -> String {
+        let hives = match auto.enable_mode {
+            WindowsEnableMode::Dynamic => "HKLM or HKCU",
+            WindowsEnableMode::CurrentUser => "HKCU",
+            WindowsEnableMode::System => "HKLM",
+        };
+        format!(
+            r"registry value {} of {}\{}",
+            auto.app_name, hives, AL_REGKEY
+        )
+    }
 }
 
 fn last_eight_bytes_all_zeros(bytes: &[u8]) -> std::result::Result<bool, &str> {
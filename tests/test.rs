@@ -1,7 +1,34 @@
 #[cfg(test)]
 mod unit_test {
-    use auto_launch::{AutoLaunch, AutoLaunchBuilder};
-    use std::env::current_dir;
+    use auto_launch::{
+        backend::Backend, sandbox, AutoLaunch, AutoLaunchBuilder, Result, Scope, Status,
+    };
+    use std::{
+        env::{current_dir, temp_dir},
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    /// Serializes the tests which read or modify the environment variables
+    #[cfg(not(target_os = "macos"))]
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn lock_env() -> std::sync::MutexGuard<'static, ()> {
+        ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get an empty temporary dir for the test
+    pub fn get_temp_dir(name: &str) -> PathBuf {
+        let dir = temp_dir().join(format!("auto-launch-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
 
     pub fn get_test_bin(name: &str) -> String {
         let ext = if cfg!(target_os = "windows") {
@@ -28,6 +55,7 @@ mod unit_test {
     #[cfg(not(target_os = "macos"))]
     #[test]
     fn test_builder() {
+        let _env = lock_env();
         let app_name = "auto-launch-test";
         let app_path = get_test_bin("auto-launch-test");
         let args = &["--minimized"];
@@ -46,13 +74,157 @@ mod unit_test {
         auto.disable().unwrap();
         assert!(!auto.is_enabled().unwrap());
     }
+
+    #[test]
+    fn test_start_hidden() {
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("the-app")
+            .set_app_path("/path/to/the-app")
+            .set_args(&["--flag"])
+            .set_use_launch_agent(true)
+            .set_start_hidden(true);
+        assert_eq!(builder.build().unwrap().get_args(), ["--flag", "--hidden"]);
+        builder.set_hidden_arg("--minimized");
+        assert_eq!(
+            builder.build().unwrap().get_args(),
+            ["--flag", "--minimized"]
+        );
+
+        // the login item of AppleScript is hidden by its property
+        let expected: &[&str] = if cfg!(target_os = "macos") {
+            &["--flag"]
+        } else {
+            &["--flag", "--minimized"]
+        };
+        builder.set_use_launch_agent(false);
+        assert_eq!(builder.build().unwrap().get_args(), expected);
+
+        builder.set_start_hidden(false);
+        assert_eq!(builder.build().unwrap().get_args(), ["--flag"]);
+    }
+
+    /// Keeps the registered command lines in memory
+    #[derive(Debug, Default)]
+    struct MemoryBackend {
+        entries: Mutex<Vec<String>>,
+    }
+
+    impl MemoryBackend {
+        fn command(auto: &AutoLaunch) -> String {
+            let mut command = auto.get_envs().len().to_string();
+            command.push(' ');
+            command.push_str(auto.get_app_path());
+            for arg in auto.get_args() {
+                command.push(' ');
+                command.push_str(arg);
+            }
+            command
+        }
+    }
+
+    impl Backend for MemoryBackend {
+        fn enable(&self, auto: &AutoLaunch) -> Result<()> {
+            self.entries.lock().unwrap().push(Self::command(auto));
+            Ok(())
+        }
+
+        fn disable(&self, auto: &AutoLaunch) -> Result<()> {
+            let command = Self::command(auto);
+            self.entries
+                .lock()
+                .unwrap()
+                .retain(|entry| entry != &command);
+            Ok(())
+        }
+
+        fn status(&self, auto: &AutoLaunch) -> Result<Status> {
+            if self.entries.lock().unwrap().contains(&Self::command(auto)) {
+                Ok(Status::Enabled)
+            } else {
+                Ok(Status::Disabled)
+            }
+        }
+
+        fn describe(&self, auto: &AutoLaunch) -> String {
+            format!("memory entry {}", auto.get_app_name())
+        }
+    }
+
+    #[test]
+    fn test_custom_backend() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("the-app")
+            .set_app_path("/path/to/the-app")
+            .set_args(&["--flag"])
+            .set_start_hidden(true)
+            .set_env("KEY", "value")
+            .set_delay(Duration::from_secs(5))
+            .set_backend(backend.clone());
+        // the options of the built-in backends are not checked
+        let auto = builder.build().unwrap();
+        assert_eq!(auto.get_delay(), Some(Duration::from_secs(5)));
+        assert_eq!(auto.describe(), "memory entry the-app");
+
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+        auto.enable().unwrap();
+        assert!(auto.is_enabled().unwrap());
+        assert_eq!(
+            *backend.entries.lock().unwrap(),
+            ["1 /path/to/the-app --flag --hidden"]
+        );
+        auto.disable().unwrap();
+        assert!(!auto.is_enabled().unwrap());
+        assert!(backend.entries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_status() {
+        assert!(Status::Enabled.is_enabled());
+        assert!(!Status::Disabled.is_enabled());
+        assert!(!Status::DisabledByUser.is_enabled());
+        assert!(Status::Stale {
+            expected: "new".into(),
+            found: "old".into()
+        }
+        .is_enabled());
+        assert!(Status::RegisteredElsewhere(Scope::System).is_enabled());
+        assert!(Status::Conflict.is_enabled());
+    }
+
+    #[test]
+    fn test_sandbox_store() {
+        let root = temp_dir().join(format!("auto-launch-sandbox-store-{}", std::process::id()));
+        let other = root.join("other");
+
+        sandbox::set_value(&root, "Key", "name", b"value");
+        sandbox::set_value(&root, "Key", "another", b"");
+        assert_eq!(
+            sandbox::get_value(&root, "Key", "name"),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(sandbox::value_names(&root, "Key"), vec!["another", "name"]);
+        // every root has its own store
+        assert_eq!(sandbox::get_value(&other, "Key", "name"), None);
+        assert!(sandbox::value_names(&other, "Key").is_empty());
+
+        assert!(sandbox::remove_value(&root, "Key", "name"));
+        assert!(!sandbox::remove_value(&root, "Key", "name"));
+        assert_eq!(sandbox::get_value(&root, "Key", "name"), None);
+
+        sandbox::clear(&root);
+        assert!(sandbox::value_names(&root, "Key").is_empty());
+    }
 }
 
 #[cfg(windows)]
 #[cfg(test)]
 mod windows_unit_test {
     use crate::unit_test::*;
-    use auto_launch::{AutoLaunch, WindowsEnableMode};
+    use auto_launch::{sandbox, AutoLaunch, AutoLaunchBuilder, Scope, Status, WindowsEnableMode};
+    use std::time::Duration;
     use windows_registry::{Key as RegKey, CURRENT_USER, LOCAL_MACHINE};
 
     const TASK_MANAGER_OVERRIDE_REGKEY: &str =
@@ -182,13 +354,254 @@ mod windows_unit_test {
 
         delete_task_manager_override_value(root_key, app_name).unwrap();
     }
+
+    #[test]
+    fn test_windows_sandbox() {
+        let app_name = "AutoLaunchSandboxTest";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("windows-sandbox");
+        let run_key = r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+        let override_key = format!(r"HKCU\{}", TASK_MANAGER_OVERRIDE_REGKEY);
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_args(&["--minimized"])
+            .set_windows_enable_mode(WindowsEnableMode::CurrentUser)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+
+        auto.enable().unwrap();
+        assert!(auto.is_enabled().unwrap());
+        assert_eq!(
+            sandbox::get_value(&root, run_key, app_name),
+            Some(format!("{} --minimized", app_path).into_bytes())
+        );
+        // the real registry is not touched
+        assert!(CURRENT_USER
+            .open(r"SOFTWARE\Microsoft\Windows\CurrentVersion\Run")
+            .and_then(|key| key.get_string(app_name))
+            .is_err());
+
+        // disabled by the task manager
+        sandbox::set_value(
+            &root,
+            &override_key,
+            app_name,
+            &TASK_MANAGER_OVERRIDE_TEST_DATA[0].1,
+        );
+        assert!(!auto.is_enabled().unwrap());
+
+        auto.disable().unwrap();
+        assert!(!auto.is_enabled().unwrap());
+        assert_eq!(sandbox::get_value(&root, run_key, app_name), None);
+
+        sandbox::clear(&root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_windows_working_directory() {
+        let app_name = "AutoLaunchSandboxTest";
+        let root = get_temp_dir("windows-working-directory");
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(r"C:\Program Files\App\app.exe")
+            .set_args(&["--minimized"])
+            .set_working_directory(r"C:\Program Files\App")
+            .set_windows_enable_mode(WindowsEnableMode::CurrentUser)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert_eq!(
+            sandbox::get_value(
+                &root,
+                r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run",
+                app_name
+            ),
+            Some(
+                r#"cmd.exe /d /c cd /d "C:\Program Files\App" && "C:\Program Files\App\app.exe" --minimized"#
+                    .as_bytes()
+                    .to_vec()
+            )
+        );
+
+        sandbox::clear(&root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_windows_status() {
+        let app_name = "AutoLaunchSandboxTest";
+        let root = get_temp_dir("windows-status");
+        let run_key = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name(app_name)
+            .set_app_path(r"C:\App\app.exe")
+            .set_args(&["--flag"])
+            .set_windows_enable_mode(WindowsEnableMode::CurrentUser)
+            .set_sandbox_root(&root);
+        let auto = builder.build().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+
+        // installed for all the users
+        let old = r"C:\Old\app.exe --flag".as_bytes();
+        sandbox::set_value(&root, &format!(r"HKLM\{}", run_key), app_name, old);
+        assert_eq!(
+            auto.status().unwrap(),
+            Status::RegisteredElsewhere(Scope::System)
+        );
+        let system = builder
+            .set_windows_enable_mode(WindowsEnableMode::System)
+            .build()
+            .unwrap();
+        assert_eq!(
+            system.status().unwrap(),
+            Status::Stale {
+                expected: r"C:\App\app.exe --flag".into(),
+                found: r"C:\Old\app.exe --flag".into(),
+            }
+        );
+        system.disable().unwrap();
+
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        // disabled in the Task Manager
+        sandbox::set_value(
+            &root,
+            &format!(r"HKCU\{}", TASK_MANAGER_OVERRIDE_REGKEY),
+            app_name,
+            &TASK_MANAGER_OVERRIDE_TEST_DATA[0].1,
+        );
+        assert_eq!(auto.status().unwrap(), Status::DisabledByUser);
+
+        sandbox::clear(&root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_windows_delay() {
+        let app_name = "AutoLaunchSandboxTest";
+        let root = get_temp_dir("windows-delay");
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(r"C:\App\app.exe")
+            .set_args(&["--flag"])
+            .set_delay(Duration::from_secs(30))
+            .set_windows_enable_mode(WindowsEnableMode::CurrentUser)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert_eq!(
+            sandbox::get_value(
+                &root,
+                r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run",
+                app_name
+            ),
+            Some(
+                r#"cmd.exe /d /c timeout /t 30 /nobreak >nul & "C:\App\app.exe" --flag"#
+                    .as_bytes()
+                    .to_vec()
+            )
+        );
+
+        sandbox::clear(&root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_windows_envs() {
+        let app_name = "AutoLaunchSandboxTest";
+        let root = get_temp_dir("windows-envs");
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(r"C:\App\app.exe")
+            .set_env("RUST_LOG", "info")
+            .set_env("QUERY", r#"a&b "c" 100%PATH% 5%& 50%"#)
+            .set_windows_enable_mode(WindowsEnableMode::CurrentUser)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert_eq!(
+            sandbox::get_value(
+                &root,
+                r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run",
+                app_name
+            ),
+            Some(
+                r#"cmd.exe /d /c set QUERY=a^&b ^"c^" 100%^PATH%^ 5%^& 50%&& set RUST_LOG=info&& "C:\App\app.exe""#
+                    .as_bytes()
+                    .to_vec()
+            )
+        );
+
+        sandbox::clear(&root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_windows_escaped_args() {
+        let app_name = "AutoLaunchSandboxTest";
+        let root = get_temp_dir("windows-escaped-args");
+        let run_key = r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+        let args = &["a&b", "50%PATH%", r#"say "hi""#, "a b", r"C:\dir\"];
+
+        // the args are quoted the same way with or without `cmd.exe`
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name(app_name)
+            .set_app_path(r"C:\App\app.exe")
+            .set_args(args)
+            .set_windows_enable_mode(WindowsEnableMode::CurrentUser)
+            .set_sandbox_root(&root);
+        builder.build().unwrap().enable().unwrap();
+        assert_eq!(
+            sandbox::get_value(&root, run_key, app_name),
+            Some(
+                r#"C:\App\app.exe a&b 50%PATH% "say \"hi\"" "a b" C:\dir\"#
+                    .as_bytes()
+                    .to_vec()
+            )
+        );
+
+        // and escaped for `cmd.exe`
+        builder
+            .set_working_directory(r"C:\100% & Co")
+            .build()
+            .unwrap()
+            .enable()
+            .unwrap();
+        assert_eq!(
+            sandbox::get_value(&root, run_key, app_name),
+            Some(
+                r#"cmd.exe /d /c cd /d "C:\100%"" & Co" && "C:\App\app.exe" a^&b 50%^PATH% ^"say \^"hi\^"^" ^"a b^" C:\dir\"#
+                    .as_bytes()
+                    .to_vec()
+            )
+        );
+
+        sandbox::clear(&root);
+        std::fs::remove_dir_all(root).unwrap();
+    }
 }
 
 #[cfg(target_os = "macos")]
 #[cfg(test)]
 mod macos_unit_test {
     use crate::unit_test::*;
-    use auto_launch::{AutoLaunch, AutoLaunchBuilder};
+    use auto_launch::{
+        sandbox, AutoLaunch, AutoLaunchBuilder, Error, KeepAlive, ProcessType, SessionType, Status,
+    };
+    use std::{fs, time::Duration};
 
     #[test]
     fn test_macos_new() {
@@ -282,16 +695,357 @@ mod macos_unit_test {
         auto.disable().unwrap();
         assert!(!auto.is_enabled().unwrap());
     }
+
+    #[test]
+    fn test_macos_home_dir() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-home-dir");
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_use_launch_agent(true)
+            .set_home_dir(&root)
+            .build()
+            .unwrap();
+        let file = root.join("Library/LaunchAgents/auto-launch-test.plist");
+
+        auto.enable().unwrap();
+        assert!(file.exists());
+        assert!(auto.is_enabled().unwrap());
+        auto.disable().unwrap();
+        assert!(!file.exists());
+        assert!(!auto.is_enabled().unwrap());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_macos_sandbox() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-sandbox");
+
+        // the login item is kept in memory
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert!(auto.is_enabled().unwrap());
+        assert_eq!(
+            sandbox::get_value(&root, sandbox::LOGIN_ITEMS_KEY, app_name),
+            Some(app_path.clone().into_bytes())
+        );
+        auto.disable().unwrap();
+        assert!(!auto.is_enabled().unwrap());
+
+        // the launch agent is written under the root
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_use_launch_agent(true)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert!(root
+            .join("Library/LaunchAgents/auto-launch-test.plist")
+            .exists());
+        auto.disable().unwrap();
+
+        sandbox::clear(&root);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_macos_envs() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-envs");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_env("RUST_LOG", "info")
+            .set_env("QUERY", "a<b && c>d")
+            .set_sandbox_root(&root);
+        assert!(matches!(
+            builder.build(),
+            Err(Error::UnsupportedOption("envs"))
+        ));
+
+        let auto = builder.set_use_launch_agent(true).build().unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join("Library/LaunchAgents/auto-launch-test.plist")).unwrap();
+        assert!(data.contains(
+            "\t<key>EnvironmentVariables</key>\n\
+            \t<dict>\n\
+            \t\t<key>QUERY</key>\n\
+            \t\t<string>a&lt;b &amp;&amp; c&gt;d</string>\n\
+            \t\t<key>RUST_LOG</key>\n\
+            \t\t<string>info</string>\n\
+            \t</dict>\n"
+        ));
+        auto.disable().unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_macos_delay() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-delay");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_args(&["--flag"])
+            .set_delay(Duration::from_secs(30))
+            .set_sandbox_root(&root);
+        assert!(matches!(
+            builder.build(),
+            Err(Error::UnsupportedOption("delay"))
+        ));
+
+        let auto = builder.set_use_launch_agent(true).build().unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join("Library/LaunchAgents/auto-launch-test.plist")).unwrap();
+        assert!(data.contains(&format!(
+            "\t<array>\n\
+            \t\t<string>/bin/sh</string>\n\
+            \t\t<string>-c</string>\n\
+            \t\t<string>sleep 30; exec \"$0\" \"$@\"</string>\n\
+            \t\t<string>{}</string>\n\
+            \t\t<string>--flag</string>\n\
+            \t</array>\n",
+            app_path
+        )));
+        auto.disable().unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_macos_launch_agent_options() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-launch-agent-options");
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_use_launch_agent(true)
+            .set_keep_alive(KeepAlive::SuccessfulExit(false))
+            .set_standard_out_path("/tmp/out & err.log")
+            .set_standard_error_path("/tmp/out & err.log")
+            .set_process_type(ProcessType::Interactive)
+            .set_limit_load_to_session_types(&[SessionType::Aqua])
+            .set_throttle_interval(10)
+            .set_associated_bundle_identifiers(&["com.example.app"])
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join("Library/LaunchAgents/auto-launch-test.plist")).unwrap();
+        assert!(data.contains(&format!(
+            "<dict>\n\
+            \t<key>Label</key>\n\
+            \t<string>auto-launch-test</string>\n\
+            \t<key>ProgramArguments</key>\n\
+            \t<array>\n\
+            \t\t<string>{}</string>\n\
+            \t</array>\n\
+            \t<key>RunAtLoad</key>\n\
+            \t<true/>\n\
+            \t<key>KeepAlive</key>\n\
+            \t<dict>\n\
+            \t\t<key>SuccessfulExit</key>\n\
+            \t\t<false/>\n\
+            \t</dict>\n\
+            \t<key>StandardOutPath</key>\n\
+            \t<string>/tmp/out &amp; err.log</string>\n\
+            \t<key>StandardErrorPath</key>\n\
+            \t<string>/tmp/out &amp; err.log</string>\n\
+            \t<key>ProcessType</key>\n\
+            \t<string>Interactive</string>\n\
+            \t<key>LimitLoadToSessionType</key>\n\
+            \t<string>Aqua</string>\n\
+            \t<key>ThrottleInterval</key>\n\
+            \t<integer>10</integer>\n\
+            \t<key>AssociatedBundleIdentifiers</key>\n\
+            \t<array>\n\
+            \t\t<string>com.example.app</string>\n\
+            \t</array>\n\
+            </dict>\n",
+            app_path
+        )));
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_use_launch_agent(true)
+            .set_keep_alive(KeepAlive::Always)
+            .set_limit_load_to_session_types(&[SessionType::Aqua, SessionType::Background])
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join("Library/LaunchAgents/auto-launch-test.plist")).unwrap();
+        assert!(data.contains("\t<key>KeepAlive</key>\n\t<true/>\n"));
+        assert!(data.contains(
+            "\t<key>LimitLoadToSessionType</key>\n\
+            \t<array>\n\
+            \t\t<string>Aqua</string>\n\
+            \t\t<string>Background</string>\n\
+            \t</array>\n"
+        ));
+        auto.disable().unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_macos_launch_agent_status() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-launch-agent-status");
+        let file = root.join("Library/LaunchAgents/auto-launch-test.plist");
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_args(&["--flag"])
+            .set_use_launch_agent(true)
+            .set_sandbox_root(&root)
+            .build()
+            .unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // rewritten as binary by `defaults`, and disabled
+        fs::write(&file, include_bytes!("fixtures/agent-binary.plist")).unwrap();
+        assert_eq!(auto.status().unwrap(), Status::DisabledByUser);
+
+        // the XML of an old install
+        let old = fs::read_to_string("tests/fixtures/agent.plist")
+            .unwrap()
+            .replace("\t<key>Disabled</key>\n\t<true/>\n", "");
+        fs::write(&file, old).unwrap();
+        assert_eq!(
+            auto.status().unwrap(),
+            Status::Stale {
+                expected: format!("{} --flag", app_path),
+                found: "'/Applications/Old App.app/Contents/MacOS/app' --flag \
+                    'a<b & \"c\" ünï ✓'"
+                    .into(),
+            }
+        );
+        assert!(auto.is_enabled().unwrap());
+
+        fs::write(&file, "not a plist").unwrap();
+        assert!(matches!(auto.status().unwrap(), Status::Stale { .. }));
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        auto.disable().unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_macos_working_directory() {
+        let app_name = "auto-launch-test";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("macos-working-directory");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name(app_name)
+            .set_app_path(&app_path)
+            .set_working_directory("/tmp")
+            .set_sandbox_root(&root);
+        // login items have no working directory
+        assert!(matches!(
+            builder.build(),
+            Err(Error::UnsupportedOption("working_directory"))
+        ));
+
+        let auto = builder.set_use_launch_agent(true).build().unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join("Library/LaunchAgents/auto-launch-test.plist")).unwrap();
+        assert!(data.contains("\t<key>WorkingDirectory</key>\n\t<string>/tmp</string>\n"));
+        auto.disable().unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
 }
 
 #[cfg(target_os = "linux")]
 #[cfg(test)]
 mod linux_unit_test {
     use crate::unit_test::*;
-    use auto_launch::AutoLaunch;
+    use auto_launch::{
+        backend::{self, Backend},
+        command::CommandRunner,
+        desktop_entry::AutostartEntry,
+        AutoLaunch, AutoLaunchBuilder, Error, GnomeAutostartPhase, KdeAutostartPhase,
+        LinuxLaunchMode, Scope, Status,
+    };
+    use std::{
+        env, fs, io,
+        os::unix::{fs::PermissionsExt, process::ExitStatusExt},
+        process::{ExitStatus, Output},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    /// Records the invocations and answers with the canned output
+    #[derive(Debug, Default)]
+    struct FakeRunner {
+        calls: Mutex<Vec<String>>,
+        stdout: Mutex<String>,
+        code: Mutex<i32>,
+    }
+
+    impl FakeRunner {
+        fn answer(&self, stdout: &str, code: i32) {
+            *self.stdout.lock().unwrap() = stdout.into();
+            *self.code.lock().unwrap() = code;
+        }
+
+        fn take_calls(&self) -> Vec<String> {
+            std::mem::take(&mut *self.calls.lock().unwrap())
+        }
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+            let mut call = vec![program];
+            call.extend_from_slice(args);
+            self.calls.lock().unwrap().push(call.join(" "));
+            Ok(Output {
+                status: ExitStatus::from_raw(*self.code.lock().unwrap() << 8),
+                stdout: self.stdout.lock().unwrap().clone().into_bytes(),
+                stderr: Vec::new(),
+            })
+        }
+    }
 
     #[test]
     fn test_linux() {
+        let _env = lock_env();
         let app_name = "AutoLaunchTest";
         let app_path = get_test_bin("auto-launch-test");
         let args = &["--minimized"];
@@ -315,4 +1069,1709 @@ mod linux_unit_test {
         auto2.disable().unwrap();
         assert!(!auto2.is_enabled().unwrap());
     }
+
+    #[test]
+    fn test_linux_xdg_dirs() {
+        let _env = lock_env();
+        let app_name = "AutoLaunchTest";
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("xdg-dirs");
+
+        let config_home = root.join("config");
+        let system_dir_1 = root.join("xdg1");
+        let system_dir_2 = root.join("xdg2");
+        env::set_var("XDG_CONFIG_HOME", &config_home);
+        env::set_var(
+            "XDG_CONFIG_DIRS",
+            env::join_paths([&system_dir_1, &system_dir_2]).unwrap(),
+        );
+
+        let auto = AutoLaunch::new(app_name, &app_path, &[] as &[&str]);
+        let file = config_home.join("autostart/AutoLaunchTest.desktop");
+
+        auto.enable().unwrap();
+        assert!(file.exists());
+        assert!(auto.is_enabled().unwrap());
+        auto.disable().unwrap();
+        assert!(!file.exists());
+        assert!(!auto.is_enabled().unwrap());
+
+        // an entry in any of the system dirs also takes effect
+        fs::create_dir_all(system_dir_2.join("autostart")).unwrap();
+        fs::write(
+            system_dir_2.join("autostart/AutoLaunchTest.desktop"),
+            "[Desktop Entry]\nType=Application\nName=AutoLaunchTest\nExec=/usr/bin/true\n",
+        )
+        .unwrap();
+        assert!(auto.is_enabled().unwrap());
+
+        // relative paths are ignored
+        env::set_var("XDG_CONFIG_HOME", "relative/config");
+        env::set_var("XDG_CONFIG_DIRS", "relative/xdg");
+        assert!(!auto.is_enabled().unwrap());
+
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_DIRS");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_home_dir() {
+        let _env = lock_env();
+        let root = get_temp_dir("linux-home-dir");
+        // the explicit home dir wins over $XDG_CONFIG_HOME
+        env::set_var("XDG_CONFIG_HOME", root.join("xdg-config"));
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_home_dir(&root);
+
+        let auto = builder.build().unwrap();
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        auto.enable().unwrap();
+        assert!(file.exists());
+        assert!(auto.is_enabled().unwrap());
+        auto.disable().unwrap();
+        assert!(!file.exists());
+
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        let unit = root.join(".config/systemd/user/AutoLaunchTest.service");
+        auto.enable().unwrap();
+        assert!(unit.exists());
+        assert!(auto.is_enabled().unwrap());
+        auto.disable().unwrap();
+        assert!(!unit.exists());
+
+        assert!(!root.join("xdg-config").exists());
+        env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_sandbox() {
+        let _env = lock_env();
+        let root = get_temp_dir("linux-sandbox");
+        // the environment is ignored in a sandbox
+        env::set_var("XDG_CONFIG_HOME", root.join("xdg-config"));
+        env::set_var("XDG_CONFIG_DIRS", root.join("xdg-dirs"));
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_sandbox_root(&root);
+
+        let auto = builder.build().unwrap();
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        auto.enable().unwrap();
+        assert!(file.exists());
+        auto.disable().unwrap();
+        assert!(!file.exists());
+
+        // the system entries are looked up under the root
+        let system_file = root.join("etc/xdg/autostart/AutoLaunchTest.desktop");
+        fs::create_dir_all(system_file.parent().unwrap()).unwrap();
+        fs::write(
+            &system_file,
+            "[Desktop Entry]\nType=Application\nName=AutoLaunchTest\nExec=/usr/bin/true\n",
+        )
+        .unwrap();
+        assert!(auto.is_enabled().unwrap());
+        auto.disable().unwrap();
+        assert!(fs::read_to_string(&file).unwrap().contains("Hidden=true"));
+        assert!(!auto.is_enabled().unwrap());
+
+        // the real systemctl is never run, an explicit runner is
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .set_use_systemctl(true)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert!(root
+            .join(".config/systemd/user/AutoLaunchTest.service")
+            .exists());
+        assert!(auto.is_enabled().unwrap());
+        auto.disable().unwrap();
+
+        let runner = Arc::new(FakeRunner::default());
+        let auto = builder.set_command_runner(runner.clone()).build().unwrap();
+        auto.enable().unwrap();
+        assert!(!runner.take_calls().is_empty());
+
+        assert!(!root.join("xdg-config").exists());
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_DIRS");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_atomic_write() {
+        let _env = lock_env();
+        let root = get_temp_dir("atomic-write");
+        env::set_var("XDG_CONFIG_HOME", &root);
+
+        let dir = root.join("autostart");
+        let file = dir.join("AutoLaunchTest.desktop");
+        fs::create_dir_all(&dir).unwrap();
+        // a long Exec which is replaced by a short one
+        let old = format!("[Desktop Entry]\nExec=/{}\n", "x".repeat(4096));
+        fs::write(&file, old).unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let auto = AutoLaunch::new("AutoLaunchTest", "/usr/bin/true", &[] as &[&str]);
+        auto.enable().unwrap();
+
+        // the file is replaced, not truncated in place, and no temporary file is left
+        let data = fs::read_to_string(&file).unwrap();
+        assert!(data.starts_with("[Desktop Entry]\n") && data.len() < 4096);
+        assert_eq!(file.metadata().unwrap().permissions().mode() & 0o777, 0o644);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        auto.disable().unwrap();
+        env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_effective_state() {
+        let _env = lock_env();
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("effective-state");
+
+        let user_dir = root.join("config/autostart");
+        let system_dir = root.join("xdg/autostart");
+        fs::create_dir_all(&user_dir).unwrap();
+        fs::create_dir_all(&system_dir).unwrap();
+        env::set_var("XDG_CONFIG_HOME", root.join("config"));
+        env::set_var("XDG_CONFIG_DIRS", root.join("xdg"));
+        env::set_var("XDG_CURRENT_DESKTOP", "ubuntu:GNOME");
+
+        let auto = AutoLaunch::new("AutoLaunchTest", &app_path, &[] as &[&str]);
+        let user_file = user_dir.join("AutoLaunchTest.desktop");
+        let system_file = system_dir.join("AutoLaunchTest.desktop");
+        let entry = |extra: &str| {
+            format!(
+                "[Desktop Entry]\nType=Application\nName=AutoLaunchTest\nExec=/usr/bin/true\n{}",
+                extra
+            )
+        };
+
+        let cases = [
+            ("", true),
+            ("Hidden=true\n", false),
+            ("Hidden=false\n", true),
+            ("X-GNOME-Autostart-enabled=false\n", false),
+            ("X-GNOME-Autostart-enabled=true\n", true),
+            ("OnlyShowIn=GNOME;Unity;\n", true),
+            ("OnlyShowIn=KDE;\n", false),
+            ("NotShowIn=GNOME;\n", false),
+            ("NotShowIn=KDE;XFCE;\n", true),
+        ];
+        for (extra, expected) in cases {
+            fs::write(&system_file, entry(extra)).unwrap();
+            assert_eq!(auto.is_enabled().unwrap(), expected, "system {:?}", extra);
+        }
+
+        // the user entry masks the system one
+        fs::write(&system_file, entry("")).unwrap();
+        for (extra, expected) in cases {
+            fs::write(&user_file, entry(extra)).unwrap();
+            assert_eq!(auto.is_enabled().unwrap(), expected, "user {:?}", extra);
+        }
+        fs::write(&system_file, entry("Hidden=true\n")).unwrap();
+        fs::write(&user_file, entry("")).unwrap();
+        assert!(auto.is_enabled().unwrap());
+
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_DIRS");
+        env::remove_var("XDG_CURRENT_DESKTOP");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_stale() {
+        let _env = lock_env();
+        let root = get_temp_dir("stale");
+        env::remove_var("XDG_CURRENT_DESKTOP");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/opt/new/app")
+            .set_args(&["--minimized"])
+            .set_home_dir(&root);
+        let auto = builder.build().unwrap();
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // quoted differently, but the same args
+        let entry = |exec: &str| {
+            format!(
+                "[Desktop Entry]\nType=Application\nName=AutoLaunchTest\nExec={}\n",
+                exec
+            )
+        };
+        fs::write(&file, entry("\"/opt/new/app\" \"--minimized\" %U")).unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // an old install path
+        fs::write(&file, entry("/opt/old/app --minimized")).unwrap();
+        assert_eq!(
+            auto.status().unwrap(),
+            Status::Stale {
+                expected: "/opt/new/app --minimized".into(),
+                found: "/opt/old/app --minimized".into(),
+            }
+        );
+        assert!(auto.is_enabled().unwrap());
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // other args, or an invalid Exec
+        for exec in [
+            "/opt/new/app",
+            "/opt/new/app --hidden",
+            "\"/opt/new/app",
+            "",
+        ] {
+            fs::write(&file, entry(exec)).unwrap();
+            assert!(
+                matches!(auto.status().unwrap(), Status::Stale { .. }),
+                "{:?}",
+                exec
+            );
+        }
+
+        // a stale entry which is not started is just disabled
+        fs::write(&file, entry("/opt/old/app") + "Hidden=true\n").unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+        auto.disable().unwrap();
+
+        // the ExecStart of the systemd user service
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        let unit = root.join(".config/systemd/user/AutoLaunchTest.service");
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        let data = fs::read_to_string(&unit).unwrap();
+        fs::write(&unit, data.replace("/opt/new/app", "/opt/old/app")).unwrap();
+        assert_eq!(
+            auto.status().unwrap(),
+            Status::Stale {
+                expected: "/opt/new/app --minimized".into(),
+                found: "/opt/old/app --minimized".into(),
+            }
+        );
+        auto.disable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_status() {
+        let _env = lock_env();
+        let root = get_temp_dir("status");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_sandbox_root(&root);
+        let auto = builder.build().unwrap();
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        let system_file = root.join("etc/xdg/autostart/AutoLaunchTest.desktop");
+        let entry = "[Desktop Entry]\nType=Application\nExec=/usr/bin/true\n";
+
+        // only installed for all the users
+        fs::create_dir_all(system_file.parent().unwrap()).unwrap();
+        fs::write(&system_file, entry).unwrap();
+        assert_eq!(
+            auto.status().unwrap(),
+            Status::RegisteredElsewhere(Scope::System)
+        );
+        assert!(auto.is_enabled().unwrap());
+
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        // turned off in the startup applications of GNOME
+        fs::write(&file, format!("{}X-GNOME-Autostart-enabled=false\n", entry)).unwrap();
+        assert_eq!(auto.status().unwrap(), Status::DisabledByUser);
+        assert!(!auto.is_enabled().unwrap());
+
+        auto.disable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+        fs::remove_file(&system_file).unwrap();
+
+        // the user service was disabled by `systemctl --user disable`
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        fs::remove_file(
+            root.join(".config/systemd/user/graphical-session.target.wants/AutoLaunchTest.service"),
+        )
+        .unwrap();
+        assert_eq!(auto.status().unwrap(), Status::DisabledByUser);
+        auto.disable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_describe() {
+        let root = get_temp_dir("describe");
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_sandbox_root(&root);
+
+        let auto = builder.build().unwrap();
+        assert_eq!(
+            auto.describe(),
+            format!(
+                "XDG autostart entry {}",
+                root.join(".config/autostart/AutoLaunchTest.desktop")
+                    .display()
+            )
+        );
+        // a built-in backend can be used directly
+        backend::Systemd.enable(&auto).unwrap();
+        assert_eq!(backend::Systemd.status(&auto).unwrap(), Status::Enabled);
+        assert_eq!(auto.status().unwrap(), Status::Disabled);
+        assert_eq!(
+            backend::Systemd.describe(&auto),
+            format!(
+                "systemd user service {}",
+                root.join(".config/systemd/user/AutoLaunchTest.service")
+                    .display()
+            )
+        );
+
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        assert_eq!(auto.describe(), backend::Systemd.describe(&auto));
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        auto.disable().unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_hidden_override() {
+        let _env = lock_env();
+        let app_path = get_test_bin("auto-launch-test");
+        let root = get_temp_dir("hidden-override");
+
+        let system_dir = root.join("xdg/autostart");
+        fs::create_dir_all(&system_dir).unwrap();
+        env::set_var("XDG_CONFIG_HOME", root.join("config"));
+        env::set_var("XDG_CONFIG_DIRS", root.join("xdg"));
+
+        let auto = AutoLaunch::new("AutoLaunchTest", &app_path, &[] as &[&str]);
+        let user_file = root.join("config/autostart/AutoLaunchTest.desktop");
+        let system_file = system_dir.join("AutoLaunchTest.desktop");
+        let system_entry =
+            "[Desktop Entry]\nType=Application\nName=AutoLaunchTest\nExec=/usr/bin/true\n";
+        fs::write(&system_file, system_entry).unwrap();
+        assert!(auto.is_enabled().unwrap());
+
+        // the system entry is masked by a user entry
+        auto.disable().unwrap();
+        assert!(!auto.is_enabled().unwrap());
+        assert!(fs::read_to_string(&user_file)
+            .unwrap()
+            .contains("\nHidden=true"));
+        assert_eq!(fs::read_to_string(&system_file).unwrap(), system_entry);
+
+        // enabling replaces the override
+        auto.enable().unwrap();
+        assert!(auto.is_enabled().unwrap());
+        assert!(!fs::read_to_string(&user_file).unwrap().contains("Hidden"));
+
+        auto.disable().unwrap();
+        assert!(!auto.is_enabled().unwrap());
+
+        // without a system entry the user entry is simply removed
+        fs::remove_file(&system_file).unwrap();
+        auto.enable().unwrap();
+        auto.disable().unwrap();
+        assert!(!user_file.exists());
+        assert!(!auto.is_enabled().unwrap());
+
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_DIRS");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_merge() {
+        let _env = lock_env();
+        let root = get_temp_dir("merge");
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(
+            &file,
+            "# added by the distro\n\
+            [Desktop Entry]\n\
+            Type=Application\n\
+            Name=Old Name\n\
+            Name[de]=Alter Name\n\
+            Icon=the-app\n\
+            Exec=/opt/old/app\n\
+            X-GNOME-Autostart-Delay=10\n\
+            X-GNOME-Autostart-enabled=false\n\
+            X-GNOME-HiddenUnderSystemd=true\n\
+            Hidden=true\n\
+            \n\
+            # the actions\n\
+            [Desktop Action New]\n\
+            Name=New Window\n\
+            Exec=/opt/old/app --new\n",
+        )
+        .unwrap();
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/opt/new/app")
+            .set_home_dir(&root)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "# added by the distro\n\
+            [Desktop Entry]\n\
+            Type=Application\n\
+            Name=AutoLaunchTest\n\
+            Name[de]=Alter Name\n\
+            Icon=the-app\n\
+            Exec=/opt/new/app\n\
+            X-GNOME-HiddenUnderSystemd=true\n\
+            Version=1.0\n\
+            Comment=AutoLaunchTest startup script\n\
+            StartupNotify=false\n\
+            Terminal=false\n\
+            \n\
+            # the actions\n\
+            [Desktop Action New]\n\
+            Name=New Window\n\
+            Exec=/opt/old/app --new\n"
+        );
+
+        // enabling again changes nothing
+        let data = fs::read_to_string(&file).unwrap();
+        auto.enable().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), data);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_desktop_options() {
+        let _env = lock_env();
+        let root = get_temp_dir("desktop-options");
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_home_dir(&root)
+            .set_only_show_in(&["GNOME", "KDE"])
+            .set_not_show_in(&["X;Y"])
+            .set_gnome_autostart_delay(30)
+            .set_gnome_autostart_phase(GnomeAutostartPhase::Panel)
+            .set_kde_autostart_phase(KdeAutostartPhase::DesktopServices);
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+
+        let data = fs::read_to_string(&file).unwrap();
+        for line in [
+            "OnlyShowIn=GNOME;KDE;\n",
+            "NotShowIn=X\\;Y;\n",
+            "X-GNOME-Autostart-Delay=30\n",
+            "X-GNOME-Autostart-Phase=Panel\n",
+            "X-KDE-autostart-phase=1\n",
+        ] {
+            assert!(data.contains(line), "{:?} in {}", line, data);
+        }
+
+        // only started in the listed desktops
+        for (desktop, expected) in [("ubuntu:GNOME", true), ("KDE", true), ("XFCE", false)] {
+            env::set_var("XDG_CURRENT_DESKTOP", desktop);
+            assert_eq!(auto.is_enabled().unwrap(), expected, "{}", desktop);
+        }
+        env::set_var("XDG_CURRENT_DESKTOP", "X;Y");
+        assert!(!auto.is_enabled().unwrap());
+
+        // an empty list removes the key, the others are left alone
+        let auto = builder
+            .set_only_show_in(&[] as &[&str])
+            .set_not_show_in(&["GNOME"])
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        let data = fs::read_to_string(&file).unwrap();
+        assert!(!data.contains("OnlyShowIn"));
+        assert!(data.contains("NotShowIn=GNOME;\n"));
+        assert!(data.contains("X-GNOME-Autostart-Delay=30\n"));
+        env::set_var("XDG_CURRENT_DESKTOP", "XFCE");
+        assert!(auto.is_enabled().unwrap());
+        env::set_var("XDG_CURRENT_DESKTOP", "GNOME");
+        assert!(!auto.is_enabled().unwrap());
+
+        env::remove_var("XDG_CURRENT_DESKTOP");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_display() {
+        let _env = lock_env();
+        let root = get_temp_dir("display");
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_home_dir(&root);
+        builder.build().unwrap().enable().unwrap();
+        let entry = AutostartEntry::parse(&fs::read_to_string(&file).unwrap());
+        assert_eq!(entry.name.as_deref(), Some("AutoLaunchTest"));
+        assert_eq!(
+            entry.comment.as_deref(),
+            Some("AutoLaunchTest startup script")
+        );
+        assert_eq!(entry.icon, None);
+
+        builder
+            .set_display_name("Auto Launch")
+            .set_icon("/opt/the app/icon.png")
+            .set_comment("Starts at login")
+            .set_localized_name("de", "Automatischer Start")
+            .set_localized_name("pt_BR", "Início\tautomático")
+            .set_localized_comment("de", "Startet bei der Anmeldung");
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+        let data = fs::read_to_string(&file).unwrap();
+        for line in [
+            "Name=Auto Launch\n",
+            "Comment=Starts at login\n",
+            "Icon=/opt/the app/icon.png\n",
+            "Name[de]=Automatischer Start\n",
+            "Name[pt_BR]=Início\\tautomático\n",
+            "Comment[de]=Startet bei der Anmeldung\n",
+        ] {
+            assert!(data.contains(line), "{:?} in {}", line, data);
+        }
+        let entry = AutostartEntry::parse(&data);
+        assert_eq!(entry.icon.as_deref(), Some("/opt/the app/icon.png"));
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // a locale cannot add another key
+        let result = builder
+            .set_localized_comment("de]\nExec=/bin/evil #", "x")
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidLocale(locale)) if locale == "de]\nExec=/bin/evil #"
+        ));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_working_directory() {
+        let root = get_temp_dir("working-directory");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_working_directory("/opt/the app/100%")
+            .set_home_dir(&root);
+        builder.build().unwrap().enable().unwrap();
+        let data = fs::read_to_string(root.join(".config/autostart/AutoLaunchTest.desktop"));
+        assert!(data.unwrap().contains("\nPath=/opt/the app/100%\n"));
+
+        builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap()
+            .enable()
+            .unwrap();
+        let data = fs::read_to_string(root.join(".config/systemd/user/AutoLaunchTest.service"));
+        assert!(data
+            .unwrap()
+            .contains("\nWorkingDirectory=/opt/the app/100%%\n"));
+
+        // a trailing backslash does not continue the line
+        builder
+            .set_working_directory("/tmp/x y\\")
+            .set_env("RUST_LOG", "info")
+            .build()
+            .unwrap()
+            .enable()
+            .unwrap();
+        let data = fs::read_to_string(root.join(".config/systemd/user/AutoLaunchTest.service"));
+        assert!(data
+            .unwrap()
+            .contains("\nWorkingDirectory=/tmp/x y\\/\nEnvironment=\"RUST_LOG=info\"\n"));
+
+        // the path is removed with the option
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_home_dir(&root);
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+        assert!(!fs::read_to_string(file).unwrap().contains("Path="));
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_envs() {
+        let _env = lock_env();
+        let root = get_temp_dir("envs");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_args(&["--minimized"])
+            .set_env("RUST_LOG", "info,app=debug")
+            .set_env("GREETING", "say \"hi\" $HOME 100%")
+            .set_home_dir(&root);
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        let entry = AutostartEntry::parse(&fs::read_to_string(file).unwrap());
+        assert_eq!(
+            entry.args().unwrap(),
+            vec![
+                "env",
+                "--",
+                "GREETING=say \"hi\" $HOME 100%",
+                "RUST_LOG=info,app=debug",
+                "/usr/bin/true",
+                "--minimized",
+            ]
+        );
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join(".config/systemd/user/AutoLaunchTest.service")).unwrap();
+        assert!(data.contains("\nEnvironment=\"GREETING=say \\\"hi\\\" $HOME 100%%\"\n"));
+        assert!(data.contains("\nEnvironment=\"RUST_LOG=info,app=debug\"\n"));
+        assert!(data.contains("\nExecStart=/usr/bin/true --minimized\n"));
+
+        for key in ["", "A=B"] {
+            let result = AutoLaunchBuilder::new()
+                .set_app_name("AutoLaunchTest")
+                .set_app_path("/usr/bin/true")
+                .set_env(key, "value")
+                .build();
+            assert!(matches!(result, Err(Error::InvalidEnvName(k)) if k == key));
+        }
+
+        // `env` would read these paths as an option or another variable
+        builder
+            .set_linux_launch_mode(LinuxLaunchMode::XdgAutostart)
+            .set_app_path("-app");
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+        let entry = AutostartEntry::parse(&fs::read_to_string(file).unwrap());
+        assert_eq!(entry.args().unwrap()[..2], ["env", "--"]);
+        assert_eq!(entry.args().unwrap()[4], "-app");
+        auto.disable().unwrap();
+        let result = builder.set_app_path("/opt/a=b/app").build();
+        assert!(matches!(
+            result,
+            Err(Error::AppPathContainsEquals(path)) if path.to_str() == Some("/opt/a=b/app")
+        ));
+        // the systemd user service sets them with `Environment`
+        builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_delay() {
+        let root = get_temp_dir("delay");
+        let file = root.join(".config/autostart/AutoLaunchTest.desktop");
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_delay(Duration::from_millis(1500))
+            .set_home_dir(&root);
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+        let entry = AutostartEntry::parse(&fs::read_to_string(&file).unwrap());
+        assert_eq!(
+            entry.args().unwrap(),
+            vec![
+                "/bin/sh",
+                "-c",
+                "sleep 2; exec \"$0\" \"$@\"",
+                "/usr/bin/true"
+            ]
+        );
+        assert_eq!(entry.gnome_autostart_delay, None);
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // an entry only shown in GNOME uses its delay
+        builder.set_only_show_in(&["GNOME"]);
+        let auto = builder.build().unwrap();
+        auto.enable().unwrap();
+        let entry = AutostartEntry::parse(&fs::read_to_string(&file).unwrap());
+        assert_eq!(entry.args().unwrap(), vec!["/usr/bin/true"]);
+        assert_eq!(entry.gnome_autostart_delay, Some(2));
+
+        // the GNOME specific delay wins
+        builder
+            .set_gnome_autostart_delay(5)
+            .build()
+            .unwrap()
+            .enable()
+            .unwrap();
+        let entry = AutostartEntry::parse(&fs::read_to_string(&file).unwrap());
+        assert_eq!(entry.gnome_autostart_delay, Some(5));
+
+        // the delay is removed with the options
+        AutoLaunchBuilder::new()
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_home_dir(&root)
+            .build()
+            .unwrap()
+            .enable()
+            .unwrap();
+        let entry = AutostartEntry::parse(&fs::read_to_string(&file).unwrap());
+        assert_eq!(entry.args().unwrap(), vec!["/usr/bin/true"]);
+        assert_eq!(entry.gnome_autostart_delay, None);
+
+        let auto = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join(".config/systemd/user/AutoLaunchTest.service")).unwrap();
+        assert!(data.contains(
+            "\nExecStartPre=/bin/sleep 2\nTimeoutStartSec=infinity\nExecStart=/usr/bin/true\n"
+        ));
+        assert_eq!(auto.status().unwrap(), Status::Enabled);
+
+        // no delay at all
+        let auto = builder.set_delay(Duration::ZERO).build().unwrap();
+        auto.enable().unwrap();
+        let data =
+            fs::read_to_string(root.join(".config/systemd/user/AutoLaunchTest.service")).unwrap();
+        assert!(!data.contains("ExecStartPre"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_systemd() {
+        let _env = lock_env();
+        let root = get_temp_dir("systemd");
+        env::set_var("XDG_CONFIG_HOME", &root);
+
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/opt/auto launch/test")
+            .set_args(&["--minimized", "50%", "$HOME", "say \"hi\""])
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        let unit_dir = root.join("systemd/user");
+        let unit = unit_dir.join("AutoLaunchTest.service");
+        let link = unit_dir.join("graphical-session.target.wants/AutoLaunchTest.service");
+
+        assert!(!auto.is_enabled().unwrap());
+        auto.enable().unwrap();
+        assert!(auto.is_enabled().unwrap());
+        assert_eq!(
+            fs::read_to_string(&unit).unwrap(),
+            "[Unit]\n\
+            Description=AutoLaunchTest\n\
+            PartOf=graphical-session.target\n\
+            After=graphical-session.target\n\
+            \n\
+            [Service]\n\
+            ExecStart=\"/opt/auto launch/test\" --minimized 50%% $$HOME \"say \\\"hi\\\"\"\n\
+            Restart=on-failure\n\
+            StandardOutput=journal\n\
+            StandardError=journal\n\
+            \n\
+            [Install]\n\
+            WantedBy=graphical-session.target\n"
+        );
+        assert_eq!(fs::read_link(&link).unwrap(), unit);
+
+        // enabling again replaces the symlink
+        auto.enable().unwrap();
+        assert!(auto.is_enabled().unwrap());
+
+        auto.disable().unwrap();
+        assert!(!auto.is_enabled().unwrap());
+        assert!(!unit.exists());
+        assert!(link.symlink_metadata().is_err());
+
+        // the unit name is escaped
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name("Auto Launch/Test")
+            .set_app_path("/usr/bin/true")
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert!(unit_dir.join("Auto\\x20Launch-Test.service").exists());
+        auto.disable().unwrap();
+
+        // the valid characters are kept
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name("my-app")
+            .set_app_path("/usr/bin/true")
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert!(unit_dir.join("my-app.service").exists());
+        assert!(unit_dir
+            .join("graphical-session.target.wants/my-app.service")
+            .symlink_metadata()
+            .is_ok());
+        auto.disable().unwrap();
+
+        // a trailing backslash does not continue the line
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name("my app\\")
+            .set_app_path("/usr/bin/true")
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        auto.enable().unwrap();
+        assert!(fs::read_to_string(unit_dir.join("my\\x20app\\x5c.service"))
+            .unwrap()
+            .starts_with("[Unit]\nDescription=my app\\\\\nPartOf=graphical-session.target\n"));
+        auto.disable().unwrap();
+
+        env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_systemctl() {
+        let _env = lock_env();
+        let root = get_temp_dir("systemctl");
+        env::set_var("XDG_CONFIG_HOME", &root);
+
+        let runner = Arc::new(FakeRunner::default());
+        let auto = AutoLaunchBuilder::new()
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true")
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .set_use_systemctl(true)
+            .set_command_runner(runner.clone())
+            .build()
+            .unwrap();
+        let unit = root.join("systemd/user/AutoLaunchTest.service");
+
+        auto.enable().unwrap();
+        assert!(unit.exists());
+        assert_eq!(
+            runner.take_calls(),
+            vec![
+                "systemctl --user daemon-reload",
+                "systemctl --user enable --now AutoLaunchTest.service",
+            ]
+        );
+
+        runner.answer("enabled\n", 0);
+        assert!(auto.is_enabled().unwrap());
+        runner.answer("disabled\n", 1);
+        assert!(!auto.is_enabled().unwrap());
+        assert_eq!(
+            runner.take_calls(),
+            vec![
+                "systemctl --user is-enabled AutoLaunchTest.service",
+                "systemctl --user is-enabled AutoLaunchTest.service",
+            ]
+        );
+
+        runner.answer("", 0);
+        auto.disable().unwrap();
+        assert!(!unit.exists());
+        assert_eq!(
+            runner.take_calls(),
+            vec![
+                "systemctl --user disable --now AutoLaunchTest.service",
+                "systemctl --user daemon-reload",
+            ]
+        );
+
+        // the failure of systemctl is reported
+        runner.answer("", 5);
+        assert!(matches!(auto.enable(), Err(Error::SystemctlFailed(5))));
+
+        env::remove_var("XDG_CONFIG_HOME");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_linux_xdg_and_systemd() {
+        let _env = lock_env();
+        let root = get_temp_dir("xdg-and-systemd");
+        env::set_var("XDG_CONFIG_HOME", &root);
+        env::set_var("XDG_CONFIG_DIRS", root.join("xdg"));
+
+        let mut builder = AutoLaunchBuilder::new();
+        builder
+            .set_app_name("AutoLaunchTest")
+            .set_app_path("/usr/bin/true");
+        let xdg = builder.build().unwrap();
+        let systemd = builder
+            .set_linux_launch_mode(LinuxLaunchMode::Systemd)
+            .build()
+            .unwrap();
+        let file = root.join("autostart/AutoLaunchTest.desktop");
+        let skip_keys = "X-systemd-skip=true\nX-GNOME-HiddenUnderSystemd=true";
+
+        // the entry written after the service is skipped under systemd
+        systemd.enable().unwrap();
+        xdg.enable().unwrap();
+        assert!(fs::read_to_string(&file).unwrap().contains(skip_keys));
+        assert!(xdg.is_enabled().unwrap() && systemd.is_enabled().unwrap());
+        assert!(!xdg.has_conflict().unwrap());
+
+        // an entry without the skip keys conflicts with the service
+        systemd.disable().unwrap();
+        assert!(!fs::read_to_string(&file)
+            .unwrap()
+            .contains("X-systemd-skip"));
+        systemd.enable().unwrap();
+        fs::write(
+            &file,
+            "[Desktop Entry]\nType=Application\nExec=/usr/bin/true\n",
+        )
+        .unwrap();
+        assert!(xdg.has_conflict().unwrap());
+        assert!(systemd.has_conflict().unwrap());
+        assert_eq!(xdg.status().unwrap(), Status::Conflict);
+        assert_eq!(systemd.status().unwrap(), Status::Conflict);
+
+        // enabling the service adds the skip keys to the existing entry
+        systemd.enable().unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            format!(
+                "[Desktop Entry]\nType=Application\nExec=/usr/bin/true\n{}\n",
+                skip_keys
+            )
+        );
+        assert!(!xdg.has_conflict().unwrap());
+        assert_eq!(xdg.status().unwrap(), Status::Enabled);
+
+        // no conflict without the service
+        systemd.disable().unwrap();
+        assert!(xdg.is_enabled().unwrap());
+        assert!(!xdg.has_conflict().unwrap());
+
+        // a system entry is masked by a copy with the skip keys
+        xdg.disable().unwrap();
+        let system_file = root.join("xdg/autostart/AutoLaunchTest.desktop");
+        let system_entry = "[Desktop Entry]\nType=Application\nExec=/usr/bin/true\n";
+        fs::create_dir_all(system_file.parent().unwrap()).unwrap();
+        fs::write(&system_file, system_entry).unwrap();
+        systemd.enable().unwrap();
+        assert_eq!(systemd.status().unwrap(), Status::Enabled);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            format!("{}{}\n", system_entry, skip_keys)
+        );
+        systemd.enable().unwrap();
+        assert_eq!(systemd.status().unwrap(), Status::Enabled);
+        assert_eq!(xdg.status().unwrap(), Status::Enabled);
+
+        // the copy is removed with the service
+        systemd.disable().unwrap();
+        assert!(!file.exists());
+        assert_eq!(fs::read_to_string(&system_file).unwrap(), system_entry);
+
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_DIRS");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod desktop_entry_test {
+    use auto_launch::desktop_entry::{
+        escape_value, format_exec, is_valid_locale, parse_exec, unescape_value, AutostartEntry,
+        DesktopEntry, MAIN_GROUP,
+    };
+
+    #[test]
+    fn test_autostart_entry() {
+        let entry = AutostartEntry::parse(
+            "# comment\n\
+            [Desktop Entry]\n\
+            Type=Application\n\
+            Name=The App\n\
+            Comment=Runs\\sthe app\n\
+            Icon=the-app\n\
+            Exec=\"/opt/the app/bin\" --flag=\"a b\" %U\n\
+            X-GNOME-Autostart-enabled=true\n\
+            OnlyShowIn=GNOME;Unity;\n\
+            NotShowIn=KDE;\n\
+            X-GNOME-Autostart-Delay=5\n\
+            X-GNOME-Autostart-Phase=Desktop\n\
+            X-KDE-autostart-phase=1\n\
+            \n\
+            [Desktop Action New]\n\
+            Exec=/usr/bin/false\n\
+            Hidden=true\n",
+        );
+        assert_eq!(entry.name.as_deref(), Some("The App"));
+        assert_eq!(entry.comment.as_deref(), Some("Runs the app"));
+        assert_eq!(entry.icon.as_deref(), Some("the-app"));
+        assert_eq!(
+            entry.exec.as_deref(),
+            Some("\"/opt/the app/bin\" --flag=\"a b\" %U")
+        );
+        assert_eq!(
+            entry.args().unwrap(),
+            vec!["/opt/the app/bin", "--flag=a b"]
+        );
+        // the keys of the other groups are ignored
+        assert!(!entry.hidden);
+        assert_eq!(entry.gnome_autostart_enabled, Some(true));
+        assert_eq!(entry.only_show_in, vec!["GNOME", "Unity"]);
+        assert_eq!(entry.not_show_in, vec!["KDE"]);
+        assert_eq!(entry.gnome_autostart_delay, Some(5));
+        assert_eq!(entry.gnome_autostart_phase.as_deref(), Some("Desktop"));
+        assert_eq!(entry.kde_autostart_phase, Some(1));
+
+        let desktops = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(entry.is_enabled_in(&desktops(&["ubuntu", "GNOME"])));
+        assert!(!entry.is_enabled_in(&desktops(&["XFCE"])));
+        assert!(!entry.is_enabled_in(&desktops(&["GNOME", "KDE"])));
+        assert!(!entry.is_enabled_in(&[]));
+
+        let entry = AutostartEntry::parse("[Desktop Entry]\nHidden=true\n");
+        assert!(entry.hidden);
+        assert!(!entry.is_enabled_in(&[]));
+        assert!(entry.args().is_err());
+        assert_eq!(AutostartEntry::parse(""), AutostartEntry::default());
+    }
+
+    #[test]
+    fn test_desktop_entry_parse() {
+        let entry = DesktopEntry::parse(
+            "# comment\n\
+            [Desktop Entry]\n\
+            Type=Application\n\
+            Name = The App \n\
+            Name[de]=Die App\n\
+            Comment=first\\nsecond\n\
+            Hidden=true\n\
+            Terminal=0\n\
+            NoDisplay=maybe\n\
+            OnlyShowIn=GNOME;KDE;Semi\\;colon\n\
+            not a key value pair\n\
+            \n\
+            [Desktop Action New]\n\
+            Name=New Window\n",
+        );
+
+        assert_eq!(entry.get_string("Name").as_deref(), Some("The App"));
+        assert_eq!(entry.get_string("Name[de]").as_deref(), Some("Die App"));
+        assert_eq!(
+            entry.get_string("Comment").as_deref(),
+            Some("first\nsecond")
+        );
+        assert_eq!(entry.get_bool("Hidden"), Some(true));
+        assert_eq!(entry.get_bool("Terminal"), Some(false));
+        assert_eq!(entry.get_bool("NoDisplay"), None);
+        assert_eq!(entry.get_bool("X-Missing"), None);
+        assert_eq!(
+            entry.get_strings("OnlyShowIn"),
+            vec!["GNOME", "KDE", "Semi;colon"]
+        );
+        assert!(entry.get_strings("NotShowIn").is_empty());
+        assert_eq!(
+            entry.get_raw("Desktop Action New", "Name"),
+            Some("New Window")
+        );
+        assert_eq!(entry.get_raw(MAIN_GROUP, "Exec"), None);
+    }
+
+    #[test]
+    fn test_desktop_entry_write() {
+        let mut entry = DesktopEntry::parse(
+            "# comment\n\
+            [Desktop Entry]\n\
+            Name=The App\n\
+            Hidden=true\n\
+            \n\
+            [Desktop Action New]\n\
+            Name=New Window\n",
+        );
+
+        entry.set_string("Name", " new\tname");
+        entry.set_bool("Terminal", false);
+        entry.remove(MAIN_GROUP, "Hidden");
+        entry.set_raw("Desktop Action New", "Exec", "app --new");
+        entry.set_raw("X-Extra", "Key", "value");
+
+        assert_eq!(
+            entry.to_string(),
+            "# comment\n\
+            [Desktop Entry]\n\
+            Name=\\snew\\tname\n\
+            Terminal=false\n\
+            \n\
+            [Desktop Action New]\n\
+            Name=New Window\n\
+            Exec=app --new\n\
+            \n\
+            [X-Extra]\n\
+            Key=value\n"
+        );
+        assert_eq!(entry.get_string("Name").as_deref(), Some(" new\tname"));
+
+        let mut entry = DesktopEntry::default();
+        entry.set_string("Name", "app");
+        assert_eq!(entry.to_string(), "[Desktop Entry]\nName=app\n");
+
+        entry.set_strings("OnlyShowIn", &["GNOME", "a;b", " c"]);
+        assert_eq!(
+            entry.get_raw(MAIN_GROUP, "OnlyShowIn"),
+            Some("GNOME;a\\;b;\\sc;")
+        );
+        assert_eq!(entry.get_strings("OnlyShowIn"), vec!["GNOME", "a;b", " c"]);
+    }
+
+    #[test]
+    fn test_desktop_entry_duplicate_keys() {
+        let mut entry = DesktopEntry::parse(
+            "[Desktop Entry]\n\
+            Hidden=false\n\
+            Exec=/old/app\n\
+            Hidden=true\n\
+            Icon=app\n\
+            \n\
+            [Desktop Action New]\n\
+            Hidden=true\n",
+        );
+        assert_eq!(entry.get_bool("Hidden"), Some(true));
+
+        // the last key wins, so it is updated and the earlier ones are removed
+        entry.set_bool("Hidden", false);
+        assert_eq!(entry.get_bool("Hidden"), Some(false));
+        assert_eq!(
+            entry.to_string(),
+            "[Desktop Entry]\n\
+            Exec=/old/app\n\
+            Hidden=false\n\
+            Icon=app\n\
+            \n\
+            [Desktop Action New]\n\
+            Hidden=true\n"
+        );
+    }
+
+    #[test]
+    fn test_locale() {
+        for locale in [
+            "de",
+            "pt_BR",
+            "en_US.UTF-8",
+            "sr@latin",
+            "sr_RS.ISO-8859-5@latin",
+        ] {
+            assert!(is_valid_locale(locale), "{}", locale);
+        }
+        for locale in [
+            "",
+            "_BR",
+            "de_",
+            "de.",
+            "de@",
+            "d e",
+            "de]",
+            "de]\nExec=/bin/evil #",
+        ] {
+            assert!(!is_valid_locale(locale), "{:?}", locale);
+        }
+    }
+
+    #[test]
+    fn test_exec_quoting() {
+        assert_eq!(
+            format_exec("/usr/bin/app", &["--minimized"]),
+            "/usr/bin/app --minimized"
+        );
+        assert_eq!(
+            format_exec("/opt/my app/app", &["a b", "", "100%"]),
+            r#""/opt/my app/app" "a b" "" 100%%"#
+        );
+        assert_eq!(
+            format_exec("/usr/bin/app", &[r#"say "hi""#, "$HOME", r"C:\dir", "`id`"]),
+            r#"/usr/bin/app "say \"hi\"" "\$HOME" "C:\\dir" "\`id\`""#
+        );
+        // backslashes are escaped again when written as a string value
+        assert_eq!(escape_value(r#""C:\\dir""#), r#""C:\\\\dir""#);
+        assert_eq!(escape_value(" a\nb "), r"\sa\nb\s");
+    }
+
+    #[test]
+    fn test_exec_round_trip() {
+        let cases: &[(&str, &[&str])] = &[
+            ("/usr/bin/app", &[]),
+            ("/usr/bin/app", &["--minimized"]),
+            ("/opt/my app/bin/app", &["--name", "my app", ""]),
+            (
+                "/usr/bin/app",
+                &[
+                    r#"quote " and ' "#,
+                    r"back\slash\\",
+                    "$dollar",
+                    "`tick`",
+                    "50%",
+                    "%u",
+                ],
+            ),
+            (
+                "/usr/bin/app",
+                &["new\nline", "tab\there", " leading", "trailing "],
+            ),
+            ("/usr/bin/app", &["<>|&;*?#()~", "ünïcødé"]),
+        ];
+
+        for (program, args) in cases {
+            let value = escape_value(&format_exec(program, args));
+            assert!(!value.contains('\n'), "{}", value);
+
+            let mut expected = vec![program.to_string()];
+            expected.extend(args.iter().map(|s| s.to_string()));
+            assert_eq!(parse_exec(&unescape_value(&value)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_exec() {
+        assert_eq!(
+            parse_exec("app %U --flag %%x \"a %% b\"").unwrap(),
+            vec!["app", "--flag", "%x", "a % b"]
+        );
+        assert_eq!(parse_exec("  app   b\t c ").unwrap(), vec!["app", "b", "c"]);
+        assert!(parse_exec("app \"unterminated").is_err());
+    }
+}
+
+#[cfg(test)]
+mod plist_test {
+    use auto_launch::{
+        plist::{self, escape_xml, Dictionary, Value},
+        Error,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// The content of the fixtures, written by Python's `plistlib`
+    fn get_agent() -> Value {
+        let mut keep_alive = Dictionary::new();
+        keep_alive.insert("SuccessfulExit", false);
+        let mut agent = Dictionary::new();
+        agent.insert("Label", "com.example.agent");
+        agent.insert(
+            "ProgramArguments",
+            vec![
+                Value::from("/Applications/Old App.app/Contents/MacOS/app"),
+                Value::from("--flag"),
+                Value::from("a<b & \"c\" ünï ✓"),
+            ],
+        );
+        agent.insert("RunAtLoad", true);
+        agent.insert("Disabled", true);
+        agent.insert("KeepAlive", keep_alive);
+        agent.insert("ThrottleInterval", 10);
+        agent.insert("Nice", -5);
+        agent.insert("Weight", 0.5);
+        agent.insert("Big", 1 << 40);
+        agent.insert(
+            "Created",
+            Value::Date(UNIX_EPOCH + Duration::from_secs(1_706_704_205)),
+        );
+        agent.insert("Token", Value::Data(b"\x00\x01binary\xff".to_vec()));
+        agent.insert("EnvironmentVariables", Dictionary::new());
+        agent.insert("Empty", Vec::new());
+        agent.insert("Many", (0..300).map(Value::Integer).collect::<Vec<_>>());
+        Value::from(agent)
+    }
+
+    #[test]
+    fn test_plist_parse_xml() {
+        let agent = plist::parse(include_bytes!("fixtures/agent.plist")).unwrap();
+        assert_eq!(agent, get_agent());
+
+        // comments, CDATA, references and an optional root element
+        let xml = "<?xml version=\"1.0\"?>\n<!-- comment -->\n\
+            <dict><key>A</key><string>x&#38;y&#x3c;<![CDATA[<&>]]><!-- c --></string>\
+            <key>B</key><integer> 0x1F </integer><key>C</key><true></true></dict>";
+        let mut expected = Dictionary::new();
+        expected.insert("A", "x&y<<&>");
+        expected.insert("B", 31);
+        expected.insert("C", true);
+        assert_eq!(plist::parse(xml.as_bytes()).unwrap(), Value::from(expected));
+    }
+
+    #[test]
+    fn test_plist_parse_binary() {
+        let data = include_bytes!("fixtures/agent-binary.plist");
+        assert!(data.starts_with(b"bplist00"));
+        assert_eq!(plist::parse(data).unwrap(), get_agent());
+
+        // every prefix is rejected without panicking
+        for len in 0..data.len() {
+            assert!(plist::parse(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_plist_round_trip() {
+        let agent = get_agent();
+        assert_eq!(plist::parse(agent.to_xml().as_bytes()).unwrap(), agent);
+    }
+
+    #[test]
+    fn test_plist_parse_invalid() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b"<plist><dict><key>A</key></dict></plist>",
+            b"<plist><dict><string>A</string><true/></dict></plist>",
+            b"<plist><array><string>unterminated</array></plist>",
+            b"<plist><integer>one</integer></plist>",
+            b"<plist><string>&unknown;</string></plist>",
+            b"<plist><date>2024-01-31</date></plist>",
+            b"<plist><data>!!</data></plist>",
+            b"<plist><true/></plist><true/>",
+            b"<plist><unknown/></plist>",
+            b"\xff\xfe",
+        ];
+        for case in cases {
+            assert!(
+                matches!(plist::parse(case), Err(Error::InvalidPlist(_))),
+                "{:?}",
+                String::from_utf8_lossy(case)
+            );
+        }
+
+        // an array which contains itself
+        let mut cyclic = b"bplist00".to_vec();
+        cyclic.extend_from_slice(&[0xa1, 0x00, 0x08]);
+        cyclic.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        cyclic.extend_from_slice(&1u64.to_be_bytes());
+        cyclic.extend_from_slice(&0u64.to_be_bytes());
+        cyclic.extend_from_slice(&10u64.to_be_bytes());
+        assert!(matches!(plist::parse(&cyclic), Err(Error::InvalidPlist(_))));
+
+        // 64 nested arrays which contain the next one twice, so 2^64 objects when expanded
+        let expanding = include_bytes!("fixtures/expanding-binary.plist");
+        assert!(matches!(
+            plist::parse(expanding),
+            Err(Error::InvalidPlist(message)) if message == "too many objects"
+        ));
+    }
+
+    #[test]
+    fn test_plist_to_xml() {
+        let mut vars = Dictionary::new();
+        vars.insert("QUERY", "a<b && c>d");
+        let mut plist = Dictionary::new();
+        plist.insert("Label", "com.example.<app>");
+        plist.insert(
+            "ProgramArguments",
+            vec![
+                Value::from("/Applications/A & B.app"),
+                Value::from("--x=\"1\""),
+            ],
+        );
+        plist.insert("RunAtLoad", true);
+        plist.insert("ThrottleInterval", 10);
+        plist.insert("Empty", Vec::new());
+        plist.insert("EnvironmentVariables", vars);
+        plist.insert("Nothing", Dictionary::new());
+
+        assert_eq!(
+            Value::from(plist).to_xml(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+            \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+            <plist version=\"1.0\">\n\
+            <dict>\n\
+            \t<key>Label</key>\n\
+            \t<string>com.example.&lt;app&gt;</string>\n\
+            \t<key>ProgramArguments</key>\n\
+            \t<array>\n\
+            \t\t<string>/Applications/A &amp; B.app</string>\n\
+            \t\t<string>--x=\"1\"</string>\n\
+            \t</array>\n\
+            \t<key>RunAtLoad</key>\n\
+            \t<true/>\n\
+            \t<key>ThrottleInterval</key>\n\
+            \t<integer>10</integer>\n\
+            \t<key>Empty</key>\n\
+            \t<array/>\n\
+            \t<key>EnvironmentVariables</key>\n\
+            \t<dict>\n\
+            \t\t<key>QUERY</key>\n\
+            \t\t<string>a&lt;b &amp;&amp; c&gt;d</string>\n\
+            \t</dict>\n\
+            \t<key>Nothing</key>\n\
+            \t<dict/>\n\
+            </dict>\n\
+            </plist>\n"
+        );
+    }
+
+    #[test]
+    fn test_plist_dictionary() {
+        let mut dict = Dictionary::new();
+        dict.insert("A", 1);
+        dict.insert("B", false);
+        // replaced in place
+        dict.insert("A", "one");
+        assert_eq!(
+            dict.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec!["A", "B"]
+        );
+        assert_eq!(dict.get("A").and_then(Value::as_str), Some("one"));
+        assert_eq!(dict.get("B").and_then(Value::as_bool), Some(false));
+        assert_eq!(dict.remove("A"), Some(Value::from("one")));
+        assert_eq!(dict.len(), 1);
+        assert_eq!(dict.get("A"), None);
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(
+            escape_xml("a & <b> \"c\" 'd'"),
+            "a &amp; &lt;b&gt; \"c\" 'd'"
+        );
+        assert_eq!(escape_xml("tab\tnew\nline"), "tab\tnew\nline");
+        // not representable in XML 1.0
+        assert_eq!(escape_xml("nul\0bell\u{7}"), "nul\u{fffd}bell\u{fffd}");
+        assert_eq!(escape_xml("ünïcødé ✓"), "ünïcødé ✓");
+    }
+}
+
+#[cfg(test)]
+mod apple_script_test {
+    use auto_launch::{
+        apple_script::{escape_string, parse_string_list, LoginItemCommand},
+        Error,
+    };
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(escape_string("the-app"), "\"the-app\"");
+        assert_eq!(escape_string(""), "\"\"");
+        assert_eq!(escape_string("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(escape_string("C:\\path\\"), "\"C:\\\\path\\\\\"");
+        assert_eq!(escape_string("a\tb\nc\rd"), "\"a\\tb\\nc\\rd\"");
+        assert_eq!(escape_string("ünï ✓ ¬"), "\"ünï ✓ ¬\"");
+        assert_eq!(
+            escape_string("nul\0bell\u{7}"),
+            "\"nul\" & (character id 0) & \"bell\" & (character id 7) & \"\""
+        );
+    }
+
+    #[test]
+    fn test_login_item_commands() {
+        assert_eq!(
+            LoginItemCommand::Make {
+                name: "The App",
+                path: "/Applications/The App.app",
+                hidden: true
+            }
+            .to_script(),
+            "tell application \"System Events\" to make login item at end with properties \
+            {name:\"The App\", path:\"/Applications/The App.app\", hidden:true}"
+        );
+        assert_eq!(
+            LoginItemCommand::Delete { name: "The App" }.to_script(),
+            "tell application \"System Events\" to delete login item \"The App\""
+        );
+        assert_eq!(
+            LoginItemCommand::GetNames.to_script(),
+            "tell application \"System Events\" to get the name of every login item"
+        );
+    }
+
+    #[test]
+    fn test_parse_string_list() {
+        // printed by `osascript -s s -e 'tell application "System Events" to get the name of every login item'`
+        assert_eq!(
+            parse_string_list(include_str!("fixtures/login-items.txt")).unwrap(),
+            [
+                "Dropbox",
+                "Docker, Inc.",
+                "say \"hi\"",
+                "back\\slash",
+                "tab\tand\nline",
+                "ünï ✓",
+                "nul\0",
+            ]
+        );
+        assert!(
+            parse_string_list(include_str!("fixtures/login-items-empty.txt"))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(parse_string_list("").unwrap().is_empty());
+        // the line breaks are not always escaped
+        assert_eq!(parse_string_list("{\"a\nb\"}\n").unwrap(), ["a\nb"]);
+
+        for invalid in [
+            "Dropbox, Docker",
+            "{\"a\" \"b\"}",
+            "{\"a\",}",
+            "{\"unterminated}",
+            "{\"a\"} {}",
+            "{\"a\" & (character id x)}",
+            "{1, 2}",
+        ] {
+            assert!(
+                matches!(
+                    parse_string_list(invalid),
+                    Err(Error::InvalidAppleScriptOutput(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_string_list_round_trip() {
+        let names = [
+            "",
+            "a, b",
+            "\", \"",
+            "\\\"",
+            "}, {",
+            "line\nbreak\r\ttab",
+            "missing value",
+            "\u{1}\u{7f}",
+        ];
+        let output = format!(
+            "{{{}}}\n",
+            names
+                .iter()
+                .map(|name| escape_string(name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        assert_eq!(parse_string_list(&output).unwrap(), names);
+    }
+
+    /// Split a script into its code, with empty string literals, and the unescaped literals
+    fn split_literals(script: &str) -> (String, Vec<String>) {
+        let (mut code, mut literals) = (String::new(), Vec::new());
+        let mut chars = script.chars();
+        while let Some(c) = chars.next() {
+            if c != '"' {
+                code.push(c);
+                continue;
+            }
+            let mut literal = String::new();
+            loop {
+                match chars.next().expect("unterminated literal") {
+                    '"' => break,
+                    '\\' => match chars.next().expect("dangling backslash") {
+                        't' => literal.push('\t'),
+                        'n' => literal.push('\n'),
+                        'r' => literal.push('\r'),
+                        c => literal.push(c),
+                    },
+                    c => literal.push(c),
+                }
+            }
+            code.push_str("\"\"");
+            literals.push(literal);
+        }
+        (code, literals)
+    }
+
+    #[test]
+    fn test_hostile_names() {
+        let names = [
+            "\" & (do shell script \"touch /tmp/pwned\") & \"",
+            "\\\" & (do shell script \"id\") & \\\"",
+            "name\\",
+            "\\\\\"\"",
+            "}, hidden:true, path:\"/bin/sh",
+            "line\n\"tell application \\\"Finder\\\" to quit",
+        ];
+        for name in names {
+            let script = LoginItemCommand::Make {
+                name,
+                path: name,
+                hidden: false,
+            }
+            .to_script();
+            // the name and path stay inside the literals, the code is unchanged
+            let (code, literals) = split_literals(&script);
+            assert_eq!(
+                code,
+                "tell application \"\" to make login item at end with properties \
+                {name:\"\", path:\"\", hidden:false}",
+                "{}",
+                script
+            );
+            assert_eq!(literals, ["System Events", name, name]);
+
+            let script = LoginItemCommand::Delete { name }.to_script();
+            let (code, literals) = split_literals(&script);
+            assert_eq!(code, "tell application \"\" to delete login item \"\"");
+            assert_eq!(literals, ["System Events", name]);
+        }
+    }
 }